@@ -0,0 +1,166 @@
+use bevy::prelude::*;
+use bevy_rapier3d::physics::RigidBodyHandleComponent;
+use bevy_rapier3d::rapier::dynamics::RigidBodySet;
+use crate::{Ball, BallEntity, Block, CurrentState, GameState, PaddleInput, PlayerEntity, Scoreboard, MAX_PLAYERS};
+use crate::highscores::HighScores;
+use crate::level::{Levels, ResetLevel};
+use crate::modes::GameMode;
+
+const IDLE_SECONDS: f32 = 20.0;
+const LEVEL_SECONDS: f32 = 45.0;
+const HIGH_SCORE_SECONDS: f32 = 8.0;
+
+enum AttractPhase {
+    Demo,
+    HighScores,
+}
+
+pub struct Attract {
+    pub active: bool,
+    phase: AttractPhase,
+    idle_seconds: f32,
+    phase_seconds: f32,
+    // The mode the player had picked, put back when the demo ends
+    player_mode: GameMode,
+}
+
+impl Default for Attract {
+    fn default() -> Self {
+        Attract {
+            active: false,
+            phase: AttractPhase::Demo,
+            idle_seconds: 0.0,
+            phase_seconds: 0.0,
+            player_mode: GameMode::Classic,
+        }
+    }
+}
+
+impl Attract {
+    pub fn banner(&self, high_scores: &HighScores) -> String {
+        match self.phase {
+            AttractPhase::Demo => "DEMO - Press any key".to_string(),
+            AttractPhase::HighScores => high_scores.table(),
+        }
+    }
+}
+
+pub fn attract_idle_system(
+    time: Res<Time>,
+    keyboard_input: Res<Input<KeyCode>>,
+    mut attract: ResMut<Attract>,
+    mut current_state: ResMut<CurrentState>,
+    mut levels: ResMut<Levels>,
+    mut scoreboard: ResMut<Scoreboard>,
+    mut mode: ResMut<GameMode>,
+    mut reset_events: ResMut<Events<ResetLevel>>,
+) {
+    let any_input = keyboard_input.get_just_pressed().next().is_some();
+
+    if attract.active {
+        if any_input {
+            // Drop straight back to the start screen with a fresh board
+            *mode = attract.player_mode;
+            *attract = Attract::default();
+            levels.current = 0;
+            scoreboard.reset();
+            reset_events.send(ResetLevel);
+            current_state.set(GameState::GameOver);
+        }
+        return;
+    }
+
+    match current_state.state {
+        GameState::ArenaStart => {
+            if any_input {
+                attract.idle_seconds = 0.0;
+            } else {
+                attract.idle_seconds += time.delta_seconds;
+            }
+
+            if attract.idle_seconds >= IDLE_SECONDS {
+                attract.active = true;
+                attract.phase = AttractPhase::Demo;
+                attract.phase_seconds = 0.0;
+                // The demo is always single player
+                attract.player_mode = *mode;
+                *mode = GameMode::Classic;
+                scoreboard.reset();
+                reset_events.send(ResetLevel);
+            }
+        },
+        _ => attract.idle_seconds = 0.0,
+    };
+}
+
+pub fn attract_cycle_system(
+    time: Res<Time>,
+    mut attract: ResMut<Attract>,
+    mut current_state: ResMut<CurrentState>,
+    mut levels: ResMut<Levels>,
+    mut scoreboard: ResMut<Scoreboard>,
+    mut reset_events: ResMut<Events<ResetLevel>>,
+    mut blocks: Query<&Block>,
+) {
+    if !attract.active {
+        return;
+    }
+
+    attract.phase_seconds += time.delta_seconds;
+
+    match attract.phase {
+        AttractPhase::Demo => {
+            let cleared = blocks.iter().iter().all(|block| !block.kind.destructible());
+            if cleared || attract.phase_seconds >= LEVEL_SECONDS {
+                attract.phase = AttractPhase::HighScores;
+                attract.phase_seconds = 0.0;
+                current_state.set(GameState::ArenaStart);
+            }
+        },
+        AttractPhase::HighScores => {
+            if attract.phase_seconds >= HIGH_SCORE_SECONDS {
+                attract.phase = AttractPhase::Demo;
+                attract.phase_seconds = 0.0;
+                levels.advance();
+                scoreboard.reset();
+                reset_events.send(ResetLevel);
+            }
+        },
+    };
+}
+
+pub fn attract_paddle_system(
+    attract: Res<Attract>,
+    current_state: Res<CurrentState>,
+    ball_entity: Res<BallEntity>,
+    player: Res<PlayerEntity>,
+    bodies: Res<RigidBodySet>,
+    mut paddle_input: ResMut<PaddleInput>,
+    balls: Query<(&Ball, &RigidBodyHandleComponent)>,
+    paddles: Query<&RigidBodyHandleComponent>,
+) {
+    if !attract.active {
+        return;
+    }
+
+    paddle_input.direction = [0.0; MAX_PLAYERS];
+    paddle_input.serve = false;
+
+    match (&current_state.state, &attract.phase) {
+        (GameState::ArenaStart, AttractPhase::Demo) => {
+            paddle_input.serve = true;
+        },
+        (GameState::Playing, AttractPhase::Demo) => {
+            let ball_handle = balls.get::<RigidBodyHandleComponent>(ball_entity.0);
+            let paddle_handle = paddles.get::<RigidBodyHandleComponent>(player.0);
+            if let (Ok(ball_handle), Ok(paddle_handle)) = (ball_handle, paddle_handle) {
+                let ball_x = bodies.get(ball_handle.handle()).unwrap().position.translation.x;
+                let paddle_x = bodies.get(paddle_handle.handle()).unwrap().position.translation.x;
+
+                // Track the ball, easing off when close so the paddle doesn't jitter
+                paddle_input.direction[0] = f32::max(-1.0, f32::min(1.0, (ball_x - paddle_x) / 2.0));
+            }
+        },
+        _ => (),
+    };
+}
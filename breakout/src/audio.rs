@@ -0,0 +1,257 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::Cursor;
+use std::sync::Arc;
+use std::time::Duration;
+use bevy::prelude::*;
+use rodio::{Decoder, Device, Source};
+use rodio::source::SineWave;
+use crate::{BallLost, BlockDestroyed, Contacts, GameState, StateChanged};
+use crate::boss::BossEvent;
+use crate::drones::DroneDestroyed;
+use crate::level::BlockKind;
+use crate::rng::Rng;
+use crate::settings::Settings;
+
+// Each playback is detuned by up to this fraction so repeated hits don't drone
+const PITCH_VARIATION: f32 = 0.08;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Sound {
+    PaddleHit,
+    SideWallHit,
+    TopWallHit,
+    BlockHit,
+    BlockBreak,
+    ToughBlockBreak,
+    PhysicsBlockBreak,
+    DroneDestroyed,
+    BossHit,
+    BallLost,
+    Serve,
+    GameOver,
+}
+
+pub enum SoundCategory {
+    Impacts,
+    Blocks,
+    Cues,
+}
+
+impl Sound {
+    fn all() -> Vec<Sound> {
+        vec![
+            Sound::PaddleHit,
+            Sound::SideWallHit,
+            Sound::TopWallHit,
+            Sound::BlockHit,
+            Sound::BlockBreak,
+            Sound::ToughBlockBreak,
+            Sound::PhysicsBlockBreak,
+            Sound::DroneDestroyed,
+            Sound::BossHit,
+            Sound::BallLost,
+            Sound::Serve,
+            Sound::GameOver,
+        ]
+    }
+
+    fn file_name(self) -> &'static str {
+        match self {
+            Sound::PaddleHit => "paddle_hit",
+            Sound::SideWallHit => "side_wall_hit",
+            Sound::TopWallHit => "top_wall_hit",
+            Sound::BlockHit => "block_hit",
+            Sound::BlockBreak => "block_break",
+            Sound::ToughBlockBreak => "tough_block_break",
+            Sound::PhysicsBlockBreak => "physics_block_break",
+            Sound::DroneDestroyed => "drone_destroyed",
+            Sound::BossHit => "boss_hit",
+            Sound::BallLost => "ball_lost",
+            Sound::Serve => "serve",
+            Sound::GameOver => "game_over",
+        }
+    }
+
+    // Fallback blip used when no recorded sound is present in assets/sounds
+    fn tone(self) -> (u32, f32) {
+        match self {
+            Sound::PaddleHit => (440, 0.06),
+            Sound::SideWallHit => (330, 0.04),
+            Sound::TopWallHit => (294, 0.04),
+            Sound::BlockHit => (660, 0.04),
+            Sound::BlockBreak => (880, 0.08),
+            Sound::ToughBlockBreak => (587, 0.14),
+            Sound::PhysicsBlockBreak => (196, 0.12),
+            Sound::DroneDestroyed => (1047, 0.1),
+            Sound::BossHit => (165, 0.2),
+            Sound::BallLost => (110, 0.4),
+            Sound::Serve => (523, 0.12),
+            Sound::GameOver => (131, 0.8),
+        }
+    }
+
+    fn category(self) -> SoundCategory {
+        match self {
+            Sound::PaddleHit | Sound::SideWallHit | Sound::TopWallHit => SoundCategory::Impacts,
+            Sound::DroneDestroyed | Sound::BossHit => SoundCategory::Impacts,
+            Sound::BlockHit | Sound::BlockBreak | Sound::ToughBlockBreak | Sound::PhysicsBlockBreak => SoundCategory::Blocks,
+            Sound::BallLost | Sound::Serve | Sound::GameOver => SoundCategory::Cues,
+        }
+    }
+}
+
+enum Clip {
+    Encoded(Arc<[u8]>),
+    Tone { frequency: u32, seconds: f32 },
+}
+
+// The null backend swallows everything, which keeps headless runs and
+// machines without an output device working.
+pub enum AudioBackend {
+    Device(Device),
+    Null,
+}
+
+impl AudioBackend {
+    pub fn detect(muted: bool) -> Self {
+        if muted {
+            return AudioBackend::Null;
+        }
+
+        match rodio::default_output_device() {
+            Some(device) => AudioBackend::Device(device),
+            None => {
+                eprintln!("No audio output device found, sound is disabled");
+                AudioBackend::Null
+            },
+        }
+    }
+
+    fn play(&self, clip: &Clip, volume: f32, pitch: f32) {
+        let device = match self {
+            AudioBackend::Device(device) => device,
+            AudioBackend::Null => return,
+        };
+
+        match clip {
+            Clip::Encoded(bytes) => {
+                if let Ok(decoder) = Decoder::new(Cursor::new(bytes.clone())) {
+                    rodio::play_raw(device, decoder.speed(pitch).amplify(volume).convert_samples());
+                }
+            },
+            Clip::Tone { frequency, seconds } => {
+                let tone = SineWave::new(*frequency)
+                    .take_duration(Duration::from_secs_f32(*seconds))
+                    .speed(pitch)
+                    .amplify(volume * 0.3);
+                rodio::play_raw(device, tone);
+            },
+        }
+    }
+}
+
+pub struct Sounds {
+    clips: HashMap<Sound, Clip>,
+    rng: Rng,
+}
+
+impl Sounds {
+    pub fn load() -> Self {
+        let mut clips = HashMap::new();
+        for sound in Sound::all() {
+            let path = format!("assets/sounds/{}.mp3", sound.file_name());
+            let clip = match fs::read(&path) {
+                Ok(bytes) => Clip::Encoded(bytes.into()),
+                Err(_) => {
+                    let (frequency, seconds) = sound.tone();
+                    Clip::Tone { frequency, seconds }
+                },
+            };
+            clips.insert(sound, clip);
+        }
+
+        Sounds {
+            clips,
+            rng: Rng::new(0),
+        }
+    }
+
+    pub fn play(&mut self, backend: &AudioBackend, settings: &Settings, sound: Sound) {
+        let category_volume = match sound.category() {
+            SoundCategory::Impacts => settings.impact_volume,
+            SoundCategory::Blocks => settings.block_volume,
+            SoundCategory::Cues => settings.cue_volume,
+        };
+        let volume = settings.master_volume * category_volume;
+        if volume <= 0.0 {
+            return;
+        }
+
+        let pitch = 1.0 + self.rng.range(-PITCH_VARIATION, PITCH_VARIATION);
+        backend.play(&self.clips[&sound], volume, pitch);
+    }
+}
+
+pub fn audio_event_system(
+    backend: Res<AudioBackend>,
+    settings: Res<Settings>,
+    mut sounds: ResMut<Sounds>,
+    mut contact_reader: Local<EventReader<Contacts>>,
+    contact_events: Res<Events<Contacts>>,
+    mut destroyed_reader: Local<EventReader<BlockDestroyed>>,
+    destroyed_events: Res<Events<BlockDestroyed>>,
+    mut drone_reader: Local<EventReader<DroneDestroyed>>,
+    drone_events: Res<Events<DroneDestroyed>>,
+    mut boss_reader: Local<EventReader<BossEvent>>,
+    boss_events: Res<Events<BossEvent>>,
+    mut lost_reader: Local<EventReader<BallLost>>,
+    lost_events: Res<Events<BallLost>>,
+    mut state_reader: Local<EventReader<StateChanged>>,
+    state_events: Res<Events<StateChanged>>,
+) {
+    for contact in contact_reader.iter(&contact_events) {
+        let sound = match contact {
+            Contacts::BallPaddle(_, _) => Sound::PaddleHit,
+            Contacts::BallSideWall(_, _) => Sound::SideWallHit,
+            Contacts::BallTopWall(_, _) => Sound::TopWallHit,
+            Contacts::BallBlock(_, _) => Sound::BlockHit,
+        };
+        sounds.play(&backend, &settings, sound);
+    }
+
+    for block in destroyed_reader.iter(&destroyed_events) {
+        let sound = match block.kind {
+            BlockKind::Tough => Sound::ToughBlockBreak,
+            BlockKind::Physics => Sound::PhysicsBlockBreak,
+            _ => Sound::BlockBreak,
+        };
+        sounds.play(&backend, &settings, sound);
+    }
+
+    for _ in drone_reader.iter(&drone_events) {
+        sounds.play(&backend, &settings, Sound::DroneDestroyed);
+    }
+
+    for event in boss_reader.iter(&boss_events) {
+        let sound = match event {
+            BossEvent::Deflected(_) => Sound::BlockHit,
+            BossEvent::WeakPointHit(_) => Sound::BossHit,
+            BossEvent::PaddleHit(_) => Sound::BallLost,
+            BossEvent::PartDestroyed(_, _) => Sound::BlockBreak,
+        };
+        sounds.play(&backend, &settings, sound);
+    }
+
+    for _ in lost_reader.iter(&lost_events) {
+        sounds.play(&backend, &settings, Sound::BallLost);
+    }
+
+    for transition in state_reader.iter(&state_events) {
+        match (transition.from, transition.to) {
+            (GameState::ArenaStart, GameState::Playing) => sounds.play(&backend, &settings, Sound::Serve),
+            (_, GameState::GameOver) => sounds.play(&backend, &settings, Sound::GameOver),
+            _ => (),
+        };
+    }
+}
@@ -0,0 +1,627 @@
+use bevy::prelude::*;
+use bevy_rapier3d::physics::RigidBodyHandleComponent;
+use bevy_rapier3d::rapier::dynamics::RigidBodySet;
+use serde::{Deserialize, Serialize};
+use crate::{
+    BallEntity, BallLost, Block, CurrentState, GameState, Lives, Paddle, PaddleInput, Scoreboard, PADDLE_HALF_WIDTH,
+};
+use crate::editor::Editor;
+use crate::level::{self, BlockKind, BlockSpec, Levels, ResetLevel};
+use crate::modes::GameMode;
+use crate::rng::Rng;
+use crate::scoring::{Combo, LevelCleared, PointsAwarded, ScoringRules};
+
+// Like drones, the boss isn't in the physics world. Its parts are boxes on
+// the board that the ball is checked against by hand.
+const BALL_RADIUS: f32 = 1.0;
+const PADDLE_HALF_DEPTH: f32 = 1.0;
+const BOSS_Y: f32 = 3.0;
+
+const HEALTH_BAR_Z: f32 = 5.5;
+const HEALTH_BAR_Y: f32 = 7.0;
+const HEALTH_BAR_HALF_WIDTH: f32 = 12.0;
+
+const PROJECTILE_RADIUS: f32 = 0.6;
+const PROJECTILE_SPREAD: f32 = 0.35;
+// Bolts past the bottom paddle are gone
+const PROJECTILE_EXIT_Z: f32 = -45.0;
+
+// Rows between the boss and the paddle where it drops new blocks, and how
+// many can be standing before it stops
+const BLOCK_ROWS: [f32; 2] = [10.0, 15.0];
+const MAX_STANDING_BLOCKS: usize = 12;
+
+// The victory sequence blows the parts off one at a time
+const PART_EXPLOSION_SECONDS: f32 = 0.3;
+const VICTORY_PAUSE_SECONDS: f32 = 1.5;
+
+fn default_health() -> usize {
+    24
+}
+
+fn default_z() -> f32 {
+    26.0
+}
+
+fn default_sway() -> f32 {
+    6.0
+}
+
+fn default_sway_speed() -> f32 {
+    4.0
+}
+
+fn default_attack_seconds() -> f32 {
+    5.0
+}
+
+fn default_attacks() -> Vec<BossAttack> {
+    vec![
+        BossAttack::Volley { count: 3, speed: 20.0 },
+        BossAttack::Blocks { count: 3 },
+    ]
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(tag = "attack", rename_all = "snake_case")]
+pub enum BossAttack {
+    // A fan of bolts from each wing towards the nearest paddle
+    Volley { count: usize, speed: f32 },
+    // New blocks dropped between the boss and the paddle
+    Blocks { count: usize },
+}
+
+// The boss section of a level file. The boss's shape is always the same; the
+// level sets how tough it is, where it sits and what it does.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct BossSpec {
+    pub name: String,
+    #[serde(default = "default_health")]
+    pub health: usize,
+    #[serde(default = "default_z")]
+    pub z: f32,
+    // Side to side, up to `sway` either way at `sway_speed` units per second
+    #[serde(default = "default_sway")]
+    pub sway: f32,
+    #[serde(default = "default_sway_speed")]
+    pub sway_speed: f32,
+    // Seconds of play between attacks, which are taken in turn
+    #[serde(default = "default_attack_seconds")]
+    pub attack_seconds: f32,
+    #[serde(default = "default_attacks")]
+    pub attacks: Vec<BossAttack>,
+}
+
+// Which side of a weak point the ball has to come in from
+#[derive(Clone, Copy, PartialEq)]
+enum Face {
+    // Towards the bottom paddle
+    Front,
+    Rear,
+    // Away from the boss's middle
+    Outer,
+}
+
+impl Face {
+    fn damage(self) -> usize {
+        match self {
+            Face::Front => 1,
+            Face::Outer => 2,
+            Face::Rear => 3,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum PartRole {
+    // Bounces the ball without taking damage
+    Armor,
+    WeakPoint(Face),
+    HealthBar,
+    HealthBarFill,
+}
+
+pub struct BossPart {
+    role: PartRole,
+    // Centre and half size on the board, relative to the boss
+    offset: Vec2,
+    half_size: Vec2,
+}
+
+impl BossPart {
+    fn new(role: PartRole, offset: (f32, f32), half_size: (f32, f32)) -> Self {
+        BossPart {
+            role,
+            offset: Vec2::new(offset.0, offset.1),
+            half_size: Vec2::new(half_size.0, half_size.1),
+        }
+    }
+
+    fn color(&self) -> Color {
+        match self.role {
+            PartRole::Armor => Color::rgb(1.2, 0.2, 1.6),
+            PartRole::WeakPoint(_) => Color::rgb(2.3, 1.0, 0.0),
+            PartRole::HealthBar => Color::rgb(0.3, 0.0, 0.0),
+            PartRole::HealthBarFill => Color::rgb(2.3, 0.0, 0.0),
+        }
+    }
+
+    fn solid(&self) -> bool {
+        match self.role {
+            PartRole::Armor | PartRole::WeakPoint(_) => true,
+            _ => false,
+        }
+    }
+
+    // Whether a box of the given half size at the point overlaps the part,
+    // which sits at `centre`
+    pub fn blocks(&self, centre: &Translation, point: Vec3, half_size: Vec2) -> bool {
+        self.solid()
+            && (centre.0.x() - point.x()).abs() < self.half_size.x() + half_size.x()
+            && (centre.0.z() - point.z()).abs() < self.half_size.y() + half_size.y()
+    }
+}
+
+// A hull with a wing either side. The eye on the front is easy to hit, the
+// wing tips need the ball to come in off the side walls, and the exhaust on
+// the back needs it to get round behind.
+fn boss_parts() -> Vec<BossPart> {
+    vec![
+        BossPart::new(PartRole::Armor, (0.0, 0.0), (8.0, 3.0)),
+        BossPart::new(PartRole::Armor, (-12.0, 1.0), (4.0, 2.0)),
+        BossPart::new(PartRole::Armor, (12.0, 1.0), (4.0, 2.0)),
+        BossPart::new(PartRole::WeakPoint(Face::Front), (0.0, -3.5), (2.0, 0.5)),
+        BossPart::new(PartRole::WeakPoint(Face::Outer), (-17.0, 1.0), (1.0, 1.5)),
+        BossPart::new(PartRole::WeakPoint(Face::Outer), (17.0, 1.0), (1.0, 1.5)),
+        BossPart::new(PartRole::WeakPoint(Face::Rear), (0.0, 3.5), (3.0, 0.5)),
+        BossPart::new(PartRole::HealthBar, (0.0, HEALTH_BAR_Z), (HEALTH_BAR_HALF_WIDTH, 0.3)),
+        BossPart::new(PartRole::HealthBarFill, (0.0, HEALTH_BAR_Z - 0.1), (HEALTH_BAR_HALF_WIDTH, 0.3)),
+    ]
+}
+
+#[derive(Clone, Copy)]
+pub enum BossEvent {
+    // The ball hit armor, or a weak point from the wrong side
+    Deflected(Vec3),
+    WeakPointHit(Vec3),
+    // A bolt caught the paddle
+    PaddleHit(Vec3),
+    PartDestroyed(Vec3, Color),
+}
+
+pub struct BossProjectile {
+    velocity: Vec3,
+    position: Vec3,
+}
+
+// The boss on the current level, if it has one
+pub struct BossFight {
+    spec: Option<BossSpec>,
+    health: usize,
+    position: Vec2,
+    sway_seconds: f32,
+    attack_seconds: f32,
+    next_attack: usize,
+    // Counts up from the final hit while the victory sequence plays
+    victory_seconds: Option<f32>,
+    rng: Rng,
+}
+
+impl Default for BossFight {
+    fn default() -> Self {
+        BossFight {
+            spec: None,
+            health: 0,
+            position: Vec2::zero(),
+            sway_seconds: 0.0,
+            attack_seconds: 0.0,
+            next_attack: 0,
+            victory_seconds: None,
+            rng: Rng::new(0),
+        }
+    }
+}
+
+impl BossFight {
+    fn active(&self) -> bool {
+        self.spec.is_some() && self.health > 0
+    }
+
+    fn start(&mut self, spec: Option<BossSpec>) {
+        self.health = spec.as_ref().map_or(0, |spec| spec.health);
+        self.position = Vec2::new(0.0, spec.as_ref().map_or(0.0, |spec| spec.z));
+        self.spec = spec;
+        self.sway_seconds = 0.0;
+        self.attack_seconds = 0.0;
+        self.next_attack = 0;
+        self.victory_seconds = None;
+    }
+
+    fn part_position(&self, part: &BossPart) -> Vec3 {
+        Vec3::new(self.position.x() + part.offset.x(), BOSS_Y, self.position.y() + part.offset.y())
+    }
+}
+
+// Builds the boss whenever the level is reset, or clears it away if the new
+// level doesn't have one
+pub fn boss_spawn_system(
+    mut commands: Commands,
+    mode: Res<GameMode>,
+    levels: Res<Levels>,
+    asset_server: Res<AssetServer>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut fight: ResMut<BossFight>,
+    mut reset_reader: Local<EventReader<ResetLevel>>,
+    reset_events: Res<Events<ResetLevel>>,
+    mut parts: Query<(Entity, &BossPart)>,
+    mut projectiles: Query<(Entity, &BossProjectile)>,
+) {
+    if reset_reader.iter(&reset_events).last().is_none() {
+        return;
+    }
+
+    for (entity, _part) in &mut parts.iter() {
+        commands.despawn(entity);
+    }
+    for (entity, _projectile) in &mut projectiles.iter() {
+        commands.despawn(entity);
+    }
+
+    fight.start(levels.for_mode(*mode).boss.clone());
+    if fight.spec.is_none() {
+        return;
+    }
+
+    // The block mesh is 8 by 2 by 2, scaled to each part's size
+    for part in boss_parts() {
+        let height = if part.role == PartRole::Armor { 1.5 } else { 1.0 };
+        let y = match part.role {
+            PartRole::HealthBar | PartRole::HealthBarFill => HEALTH_BAR_Y,
+            _ => BOSS_Y,
+        };
+        let mut position = fight.part_position(&part);
+        position.set_y(y);
+        commands
+            .spawn(PbrComponents {
+                mesh: asset_server
+                    .load("assets/blender/block/export/block.gltf")
+                    .unwrap(),
+                material: materials.add(part.color().into()),
+                translation: Translation(position),
+                ..Default::default()
+            })
+            .with(NonUniformScale(Vec3::new(part.half_size.x() / 4.0, height, part.half_size.y())))
+            .with(part);
+    }
+}
+
+// Sways the boss and keeps its parts and health bar in place
+pub fn boss_movement_system(
+    time: Res<Time>,
+    current_state: Res<CurrentState>,
+    mut fight: ResMut<BossFight>,
+    mut parts: Query<(&BossPart, Mut<Translation>, Mut<NonUniformScale>)>,
+) {
+    let (sway, sway_speed, max_health) = match &fight.spec {
+        Some(spec) => (spec.sway, spec.sway_speed, spec.health),
+        None => return,
+    };
+
+    if current_state.state == GameState::Playing && fight.active() {
+        fight.sway_seconds += time.delta_seconds;
+        let x = if sway > 0.0 { sway * (fight.sway_seconds * sway_speed / sway).sin() } else { 0.0 };
+        fight.position.set_x(x);
+    }
+
+    let fraction = fight.health as f32 / usize::max(1, max_health) as f32;
+    for (part, mut translation, mut scale) in &mut parts.iter() {
+        let y = translation.0.y();
+        translation.0 = fight.part_position(part);
+        translation.0.set_y(y);
+
+        // The fill shrinks towards the left end of the bar as seen by the player
+        if part.role == PartRole::HealthBarFill {
+            let x = translation.0.x() + HEALTH_BAR_HALF_WIDTH * (1.0 - fraction);
+            translation.0.set_x(x);
+            scale.0.set_x(fraction * HEALTH_BAR_HALF_WIDTH / 4.0);
+        }
+    }
+}
+
+// Bounces the ball off the boss and works out the damage. A weak point only
+// counts when the ball comes in through its open side.
+pub fn boss_hit_system(
+    current_state: Res<CurrentState>,
+    rules: Res<ScoringRules>,
+    ball_entity: Res<BallEntity>,
+    combo: Res<Combo>,
+    mut bodies: ResMut<RigidBodySet>,
+    mut scoreboard: ResMut<Scoreboard>,
+    mut fight: ResMut<BossFight>,
+    mut points_events: ResMut<Events<PointsAwarded>>,
+    mut boss_events: ResMut<Events<BossEvent>>,
+    mut cleared_events: ResMut<Events<LevelCleared>>,
+    mut parts: Query<&BossPart>,
+    handles: Query<&RigidBodyHandleComponent>,
+) {
+    if current_state.state != GameState::Playing || !fight.active() {
+        return;
+    }
+    let ball_handle = match handles.get::<RigidBodyHandleComponent>(ball_entity.0) {
+        Ok(handle) => handle.handle(),
+        Err(_) => return,
+    };
+    let ball = bodies.get(ball_handle).unwrap().position.translation;
+
+    for part in &mut parts.iter() {
+        if !part.solid() {
+            continue;
+        }
+        let position = fight.part_position(part);
+        let (dx, dz) = (ball.x - position.x(), ball.z - position.z());
+        let overlap_x = part.half_size.x() + BALL_RADIUS - dx.abs();
+        let overlap_z = part.half_size.y() + BALL_RADIUS - dz.abs();
+        if overlap_x <= 0.0 || overlap_z <= 0.0 {
+            continue;
+        }
+
+        // The side the ball is least far into is the one it came through
+        let mut ball_body = bodies.get_mut(ball_handle).unwrap();
+        let (normal_x, normal_z) = if overlap_x < overlap_z { (dx.signum(), 0.0) } else { (0.0, dz.signum()) };
+        if ball_body.linvel.x * normal_x + ball_body.linvel.z * normal_z >= 0.0 {
+            continue;
+        }
+        if normal_x != 0.0 {
+            ball_body.linvel.x = ball_body.linvel.x.abs() * normal_x;
+        } else {
+            ball_body.linvel.z = ball_body.linvel.z.abs() * normal_z;
+        }
+
+        let open = match part.role {
+            PartRole::WeakPoint(Face::Front) => normal_z < 0.0,
+            PartRole::WeakPoint(Face::Rear) => normal_z > 0.0,
+            PartRole::WeakPoint(Face::Outer) => normal_x != 0.0 && normal_x == part.offset.x().signum(),
+            _ => false,
+        };
+        let damage = match part.role {
+            PartRole::WeakPoint(face) if open => face.damage(),
+            _ => 0,
+        };
+        if damage == 0 {
+            boss_events.send(BossEvent::Deflected(position));
+            break;
+        }
+
+        fight.health = fight.health.saturating_sub(damage);
+        let mut points = rules.boss_hit_points * damage * combo.multiplier;
+        if fight.health == 0 {
+            points += rules.boss_defeat_points;
+            fight.victory_seconds = Some(0.0);
+            cleared_events.send(LevelCleared);
+        }
+        scoreboard.score += points;
+        let last_touch = scoreboard.last_touch;
+        scoreboard.players[last_touch] += points;
+        points_events.send(PointsAwarded { points, position });
+        boss_events.send(BossEvent::WeakPointHit(position));
+        break;
+    }
+}
+
+// Takes the boss's attacks in turn and flies its bolts. A bolt that catches a
+// paddle costs a life.
+pub fn boss_attack_system(
+    mut commands: Commands,
+    time: Res<Time>,
+    mode: Res<GameMode>,
+    asset_server: Res<AssetServer>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut current_state: ResMut<CurrentState>,
+    mut fight: ResMut<BossFight>,
+    mut lives: ResMut<Lives>,
+    bodies: Res<RigidBodySet>,
+    mut boss_events: ResMut<Events<BossEvent>>,
+    mut lost_reader: Local<EventReader<BallLost>>,
+    lost_events: Res<Events<BallLost>>,
+    mut projectiles: Query<(Entity, Mut<BossProjectile>, Mut<Translation>)>,
+    mut paddles: Query<(&Paddle, &RigidBodyHandleComponent)>,
+    mut blocks: Query<(&Block, &RigidBodyHandleComponent)>,
+) {
+    // Losing the ball clears the bolts away along with it
+    if lost_reader.iter(&lost_events).last().is_some() {
+        for (entity, _projectile, _translation) in &mut projectiles.iter() {
+            commands.despawn(entity);
+        }
+        return;
+    }
+    if current_state.state != GameState::Playing {
+        return;
+    }
+
+    let mut paddle_positions = vec![];
+    for (paddle, handle) in &mut paddles.iter() {
+        if let Some(body) = bodies.get(handle.handle()) {
+            let translation = body.position.translation;
+            // Lives go by the goal the paddle guards, the same as losing the
+            // ball, so both co-op paddles share the first count
+            let goal = if paddle.home_z < 0.0 { 0 } else { 1 };
+            paddle_positions.push((goal, Vec3::new(translation.x, translation.y, translation.z)));
+        }
+    }
+
+    for (entity, mut projectile, mut translation) in &mut projectiles.iter() {
+        let step = projectile.velocity * time.delta_seconds;
+        projectile.position += step;
+        translation.0 = projectile.position;
+
+        let position = projectile.position;
+        let caught = paddle_positions.iter().find(|(_goal, paddle)| {
+            (paddle.x() - position.x()).abs() < PADDLE_HALF_WIDTH + PROJECTILE_RADIUS
+                && (paddle.z() - position.z()).abs() < PADDLE_HALF_DEPTH + PROJECTILE_RADIUS
+        });
+        if let Some((goal, _paddle)) = caught {
+            commands.despawn(entity);
+            boss_events.send(BossEvent::PaddleHit(position));
+            if mode.has_lives() {
+                lives.count[*goal] = lives.count[*goal].saturating_sub(1);
+                if lives.count[*goal] == 0 {
+                    current_state.set(GameState::GameOver);
+                }
+            }
+        } else if position.z() < PROJECTILE_EXIT_Z {
+            commands.despawn(entity);
+        }
+    }
+
+    let attack_seconds = match &fight.spec {
+        Some(spec) if fight.active() => spec.attack_seconds,
+        _ => return,
+    };
+    fight.attack_seconds += time.delta_seconds;
+    if fight.attack_seconds < attack_seconds {
+        return;
+    }
+    fight.attack_seconds = 0.0;
+
+    let attacks = &fight.spec.as_ref().unwrap().attacks;
+    if attacks.is_empty() {
+        return;
+    }
+    let attack = attacks[fight.next_attack % attacks.len()].clone();
+    fight.next_attack += 1;
+
+    match attack {
+        BossAttack::Volley { count, speed } => {
+            let material = materials.add(Color::rgb(2.3, 0.6, 0.0).into());
+            for wing_x in [-12.0, 12.0].iter() {
+                let origin = Vec3::new(fight.position.x() + wing_x, BOSS_Y, fight.position.y() - 3.0);
+                let target = paddle_positions
+                    .iter()
+                    .filter(|(_goal, paddle)| paddle.z() < origin.z())
+                    .map(|(_goal, paddle)| *paddle)
+                    .min_by(|a, b| (a.x() - origin.x()).abs().partial_cmp(&(b.x() - origin.x()).abs()).unwrap())
+                    .unwrap_or(Vec3::new(origin.x(), BOSS_Y, -35.0));
+                let aim = (target.x() - origin.x()).atan2(origin.z() - target.z());
+
+                for index in 0..count {
+                    let spread = if count > 1 {
+                        PROJECTILE_SPREAD * (index as f32 / (count - 1) as f32 - 0.5) * 2.0
+                    } else {
+                        0.0
+                    };
+                    let angle = aim + spread;
+                    let velocity = Vec3::new(angle.sin(), 0.0, -angle.cos()) * speed;
+                    commands
+                        .spawn(PbrComponents {
+                            mesh: asset_server
+                                .load("assets/blender/ball/export/ball.gltf")
+                                .unwrap(),
+                            material,
+                            translation: Translation(origin),
+                            scale: Scale(PROJECTILE_RADIUS / BALL_RADIUS),
+                            ..Default::default()
+                        })
+                        .with(BossProjectile {
+                            velocity,
+                            position: origin,
+                        });
+                }
+            }
+        },
+        BossAttack::Blocks { count } => {
+            let mut standing = vec![];
+            for (block, handle) in &mut blocks.iter() {
+                if let Some(body) = bodies.get(handle.handle()) {
+                    if block.kind.destructible() {
+                        let translation = body.position.translation;
+                        standing.push((translation.x, translation.z));
+                    }
+                }
+            }
+
+            let mut free = vec![];
+            for z in BLOCK_ROWS.iter() {
+                for column in 0..6 {
+                    let x = -25.0 + column as f32 * 10.0;
+                    if !standing.iter().any(|(bx, bz)| (bx - x).abs() < 8.0 && (bz - z).abs() < 2.0) {
+                        free.push((x, *z));
+                    }
+                }
+            }
+
+            let room = MAX_STANDING_BLOCKS.saturating_sub(standing.len());
+            for _ in 0..usize::min(count, room) {
+                if free.is_empty() {
+                    break;
+                }
+                let (x, z) = free.remove(fight.rng.below(free.len()));
+                level::spawn_block(
+                    &mut commands,
+                    &BlockSpec {
+                        x,
+                        z,
+                        color: Color::rgb(1.2, 0.2, 1.6),
+                        kind: BlockKind::Standard,
+                        motion: None,
+                    },
+                );
+            }
+        },
+    }
+}
+
+// Once the boss is beaten its parts blow off one by one, then the campaign
+// moves on to the next level. Serving is held off until it's done.
+pub fn boss_victory_system(
+    mut commands: Commands,
+    time: Res<Time>,
+    mode: Res<GameMode>,
+    editor: Res<Editor>,
+    mut levels: ResMut<Levels>,
+    mut current_state: ResMut<CurrentState>,
+    mut paddle_input: ResMut<PaddleInput>,
+    mut fight: ResMut<BossFight>,
+    mut boss_events: ResMut<Events<BossEvent>>,
+    mut reset_events: ResMut<Events<ResetLevel>>,
+    mut parts: Query<(Entity, &BossPart, &Translation)>,
+) {
+    let seconds = match fight.victory_seconds {
+        Some(seconds) => seconds + time.delta_seconds,
+        None => return,
+    };
+    fight.victory_seconds = Some(seconds);
+    paddle_input.serve = false;
+
+    // The final hit leaves the ball in play for a frame so modes watching for
+    // LevelCleared, like time attack, see the clear while still playing
+    if current_state.state == GameState::Playing {
+        current_state.set(GameState::ArenaStart);
+    }
+
+    // One part goes every so often
+    let mut remaining: Vec<(Entity, Vec3, Color)> = vec![];
+    for (entity, part, translation) in &mut parts.iter() {
+        remaining.push((entity, translation.0, part.color()));
+    }
+    let due = (seconds / PART_EXPLOSION_SECONDS) as usize;
+    let exploded = boss_parts().len() - remaining.len();
+    if due > exploded {
+        if let Some((entity, position, color)) = remaining.last() {
+            commands.despawn(*entity);
+            boss_events.send(BossEvent::PartDestroyed(*position, *color));
+        }
+        return;
+    }
+
+    let sequence_seconds = boss_parts().len() as f32 * PART_EXPLOSION_SECONDS + VICTORY_PAUSE_SECONDS;
+    if seconds < sequence_seconds {
+        return;
+    }
+
+    fight.victory_seconds = None;
+    fight.spec = None;
+    if mode.uses_campaign() && !editor.playtesting {
+        levels.advance();
+    }
+    reset_events.send(ResetLevel);
+}
@@ -0,0 +1,171 @@
+use bevy::prelude::*;
+use bevy::render::camera::{Camera, PerspectiveProjection};
+use bevy_rapier3d::physics::RigidBodyHandleComponent;
+use bevy_rapier3d::rapier::dynamics::RigidBodySet;
+use serde::{Deserialize, Serialize};
+use crate::{GameCamera, PlayerEntity};
+use crate::editor::Editor;
+use crate::settings::Settings;
+
+const TRANSITION_SECONDS: f32 = 0.8;
+
+// Half the board's length and width plus a margin, used for the orthographic view
+const ORTHOGRAPHIC_HALF_HEIGHT: f32 = 45.0;
+const ORTHOGRAPHIC_HALF_WIDTH: f32 = 36.0;
+
+// The perspective views were framed for a 16:9 window
+const DESIGN_ASPECT_RATIO: f32 = 16.0 / 9.0;
+
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CameraMode {
+    Overhead,
+    TopDown,
+    Chase,
+    Side,
+}
+
+impl CameraMode {
+    fn next(self) -> CameraMode {
+        match self {
+            CameraMode::Overhead => CameraMode::TopDown,
+            CameraMode::TopDown => CameraMode::Chase,
+            CameraMode::Chase => CameraMode::Side,
+            CameraMode::Side => CameraMode::Overhead,
+        }
+    }
+
+    fn view(self, paddle_x: f32) -> View {
+        match self {
+            CameraMode::Overhead => View {
+                eye: Vec3::new(0.0, 60.0, -85.0),
+                target: Vec3::new(0.0, 0.0, -10.0),
+                orthographic: 0.0,
+            },
+            // Straight down from high above; the small z offset keeps the
+            // board's far end at the top of the screen
+            CameraMode::TopDown => View {
+                eye: Vec3::new(0.0, 200.0, -2.0),
+                target: Vec3::new(0.0, 0.0, 0.0),
+                orthographic: 1.0,
+            },
+            CameraMode::Chase => View {
+                eye: Vec3::new(paddle_x, 12.0, -62.0),
+                target: Vec3::new(paddle_x * 0.5, 0.0, 0.0),
+                orthographic: 0.0,
+            },
+            CameraMode::Side => View {
+                eye: Vec3::new(-50.0, 2.0, -30.0),
+                target: Vec3::new(0.0, 0.0, -30.0),
+                orthographic: 0.0,
+            },
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct View {
+    eye: Vec3,
+    target: Vec3,
+    // 0.0 is the camera's perspective projection, 1.0 fully orthographic
+    orthographic: f32,
+}
+
+impl View {
+    fn lerp(self, other: View, t: f32) -> View {
+        View {
+            eye: self.eye + (other.eye - self.eye) * t,
+            target: self.target + (other.target - self.target) * t,
+            orthographic: self.orthographic + (other.orthographic - self.orthographic) * t,
+        }
+    }
+}
+
+pub struct CameraRig {
+    from: Option<View>,
+    current: Option<View>,
+    progress: f32,
+}
+
+impl Default for CameraRig {
+    fn default() -> Self {
+        CameraRig {
+            from: None,
+            current: None,
+            progress: 1.0,
+        }
+    }
+}
+
+pub fn camera_cycle_system(
+    keyboard_input: Res<Input<KeyCode>>,
+    editor: Res<Editor>,
+    mut settings: ResMut<Settings>,
+    mut rig: ResMut<CameraRig>,
+) {
+    if keyboard_input.just_pressed(KeyCode::C) && !editor.naming {
+        settings.camera_mode = settings.camera_mode.next();
+        settings.save();
+
+        // Blend from wherever the camera is right now, even mid-transition
+        rig.from = rig.current;
+        rig.progress = 0.0;
+    }
+}
+
+pub fn camera_mode_system(
+    time: Res<Time>,
+    settings: Res<Settings>,
+    player: Res<PlayerEntity>,
+    bodies: Res<RigidBodySet>,
+    mut rig: ResMut<CameraRig>,
+    paddles: Query<&RigidBodyHandleComponent>,
+    mut cameras: Query<(Mut<GameCamera>, Mut<Camera>, &PerspectiveProjection)>,
+) {
+    let mut paddle_x = 0.0;
+    if let Ok(handle) = paddles.get::<RigidBodyHandleComponent>(player.0) {
+        paddle_x = bodies.get(handle.handle()).unwrap().position.translation.x;
+    }
+
+    rig.progress = f32::min(1.0, rig.progress + time.delta_seconds / TRANSITION_SECONDS);
+    // Smoothstep so the camera eases in and out of each move
+    let t = rig.progress * rig.progress * (3.0 - 2.0 * rig.progress);
+
+    let target = settings.camera_mode.view(paddle_x);
+    let view = match rig.from {
+        Some(from) => from.lerp(target, t),
+        None => target,
+    };
+    rig.current = Some(view);
+
+    for (mut game_camera, mut camera, perspective) in &mut cameras.iter() {
+        game_camera.eye = view.eye;
+        game_camera.target = view.target;
+
+        let aspect_ratio = perspective.aspect_ratio;
+
+        // Narrower windows widen the vertical field of view so the board's
+        // full width stays in frame; wider ones keep the designed framing
+        let design_tan = (perspective.fov / 2.0).tan();
+        let fitted_tan = f32::max(design_tan, design_tan * DESIGN_ASPECT_RATIO / aspect_ratio);
+        let projection = Mat4::perspective_rh(
+            2.0 * fitted_tan.atan(),
+            aspect_ratio,
+            perspective.near,
+            perspective.far,
+        );
+
+        let half_height = f32::max(ORTHOGRAPHIC_HALF_HEIGHT, ORTHOGRAPHIC_HALF_WIDTH / aspect_ratio);
+        let half_width = half_height * aspect_ratio;
+        let orthographic = Mat4::orthographic_rh(
+            -half_width,
+            half_width,
+            -half_height,
+            half_height,
+            perspective.near,
+            perspective.far,
+        );
+        camera.projection_matrix = projection * (1.0 - view.orthographic)
+            + orthographic * view.orthographic;
+    }
+}
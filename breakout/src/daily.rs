@@ -0,0 +1,308 @@
+use std::fs;
+use std::time::{SystemTime, UNIX_EPOCH};
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use crate::{CurrentState, GameState, Lives, RunOver, StateChanged, START_LIVES};
+use crate::generator;
+use crate::level::{BlockKind, Level, Levels, ResetLevel};
+use crate::modes::GameMode;
+use crate::rng::Rng;
+use crate::scoring::LevelCleared;
+
+const DAILY_PATH: &str = "daily.json";
+
+// Keeps the daily seeds apart from endless runs started with the same number
+const SEED_SALT: u64 = 0xDA11_C4A1_1E46_E000;
+
+// Generator stage the daily level is built at, or with the dense modifier
+const STAGE: usize = 3;
+const DENSE_STAGE: usize = 8;
+const FAST_BALL_EXTRA_SPEED: f32 = 8.0;
+const PREVIOUS_RUNS_SHOWN: usize = 5;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Modifier {
+    FastBall,
+    Armored,
+    SuddenDeath,
+    Dense,
+}
+
+impl Modifier {
+    fn name(self) -> &'static str {
+        match self {
+            Modifier::FastBall => "Fast ball",
+            Modifier::Armored => "Armored blocks",
+            Modifier::SuddenDeath => "One life",
+            Modifier::Dense => "Dense field",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct DailyRun {
+    date: String,
+    day: u64,
+    score: usize,
+    seconds: f32,
+    cleared: bool,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct DailyHistory {
+    runs: Vec<DailyRun>,
+}
+
+pub struct Daily {
+    day: u64,
+    date: String,
+    modifiers: Vec<Modifier>,
+    history: DailyHistory,
+    // The run under way is the day's scored attempt
+    scored: bool,
+    // Set when the current run broke the last block
+    cleared: bool,
+    // Shown on the start screen after a daily run ends
+    results: Option<String>,
+}
+
+// Days since the epoch to a (year, month, day) UTC date
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month as u32, day as u32)
+}
+
+fn today() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs() / 86_400)
+        .unwrap_or(0)
+}
+
+fn modifiers_for(day: u64) -> Vec<Modifier> {
+    let mut rng = Rng::new(day ^ SEED_SALT);
+    let all = [Modifier::FastBall, Modifier::Armored, Modifier::SuddenDeath, Modifier::Dense];
+    let mut modifiers: Vec<Modifier> = all.iter().cloned().filter(|_| rng.chance(0.35)).collect();
+    if modifiers.is_empty() {
+        modifiers.push(all[rng.below(all.len())]);
+    }
+    modifiers
+}
+
+fn format_seconds(seconds: f32) -> String {
+    let seconds = seconds as usize;
+    format!("{:02}:{:02}", seconds / 60, seconds % 60)
+}
+
+impl Daily {
+    pub fn load() -> Self {
+        let history = match fs::read_to_string(DAILY_PATH) {
+            Ok(contents) => serde_json::from_str(&contents).unwrap_or_else(|e| {
+                eprintln!("Ignoring invalid {}: {}", DAILY_PATH, e);
+                DailyHistory::default()
+            }),
+            Err(_) => DailyHistory::default(),
+        };
+
+        let mut daily = Daily {
+            day: 0,
+            date: String::new(),
+            modifiers: vec![],
+            history,
+            scored: false,
+            cleared: false,
+            results: None,
+        };
+        daily.refresh();
+        daily
+    }
+
+    // Picks up the new day's challenge when the game runs past midnight
+    fn refresh(&mut self) {
+        let day = today();
+        let (year, month, day_of_month) = civil_from_days(day as i64);
+        self.day = day;
+        self.date = format!("{}-{:02}-{:02}", year, month, day_of_month);
+        self.modifiers = modifiers_for(day);
+    }
+
+    fn has(&self, modifier: Modifier) -> bool {
+        self.modifiers.contains(&modifier)
+    }
+
+    fn todays_run(&self) -> Option<&DailyRun> {
+        self.history.runs.iter().find(|run| run.day == self.day)
+    }
+
+    // The attempt is used up as soon as the first serve of the day goes out,
+    // so quitting halfway doesn't earn a retry
+    fn start_attempt(&mut self) {
+        if self.todays_run().is_some() {
+            return;
+        }
+        self.scored = true;
+        self.history.runs.push(DailyRun {
+            date: self.date.clone(),
+            day: self.day,
+            score: 0,
+            seconds: 0.0,
+            cleared: false,
+        });
+        self.save();
+    }
+
+    fn lives(&self) -> usize {
+        if self.has(Modifier::SuddenDeath) { 1 } else { START_LIVES }
+    }
+
+    fn level(&self) -> Level {
+        let stage = if self.has(Modifier::Dense) { DENSE_STAGE } else { STAGE };
+        let mut level = generator::generate(self.day ^ SEED_SALT, stage);
+        level.name = format!("Daily {}", self.date);
+        if self.has(Modifier::FastBall) {
+            level.ball_speed += FAST_BALL_EXTRA_SPEED;
+        }
+        if self.has(Modifier::Armored) {
+            for block in level.blocks.iter_mut().filter(|block| block.kind == BlockKind::Standard) {
+                block.kind = BlockKind::Tough;
+            }
+        }
+        level
+    }
+
+    pub fn banner(&self) -> String {
+        let mut banner = String::new();
+        if let Some(results) = &self.results {
+            banner += results;
+            banner += "\n\n";
+        }
+        let modifiers: Vec<&str> = self.modifiers.iter().map(|modifier| modifier.name()).collect();
+        banner += &format!("Daily {}: {}\n", self.date, modifiers.join(", "));
+        if !self.scored {
+            if let Some(run) = self.todays_run() {
+                banner += &format!("Today's attempt scored {} - practice only\n", run.score);
+            }
+        }
+        banner
+    }
+
+    // Only the first run of the day is kept; later ones are practice
+    fn finish(&mut self, score: usize, seconds: f32) -> String {
+        let outcome = if self.cleared {
+            format!("cleared in {}", format_seconds(seconds))
+        } else {
+            format!("out after {}", format_seconds(seconds))
+        };
+
+        if !self.scored {
+            let todays_score = self.todays_run().map_or(0, |run| run.score);
+            return format!("PRACTICE {} - {}\nToday's scored attempt: {}", score, outcome, todays_score);
+        }
+
+        self.scored = false;
+        let (day, cleared) = (self.day, self.cleared);
+        if let Some(run) = self.history.runs.iter_mut().find(|run| run.day == day) {
+            run.score = score;
+            run.seconds = seconds;
+            run.cleared = cleared;
+        }
+        self.save();
+
+        let runs = &self.history.runs;
+        let best = runs.iter().map(|run| run.score).max().unwrap_or(0);
+        let average = runs.iter().map(|run| run.score).sum::<usize>() / runs.len();
+        let rank = 1 + runs.iter().filter(|run| run.score > score).count();
+        let mut results = format!(
+            "DAILY {} - {} {}\nRank {} of {} days   Best {}   Average {}",
+            self.date,
+            score,
+            outcome,
+            rank,
+            runs.len(),
+            best,
+            average,
+        );
+
+        let previous: Vec<String> = runs
+            .iter()
+            .rev()
+            .skip(1)
+            .take(PREVIOUS_RUNS_SHOWN)
+            .map(|run| format!("{} {}{}", run.date, run.score, if run.cleared { "*" } else { "" }))
+            .collect();
+        if !previous.is_empty() {
+            results += &format!("\nPrevious: {}", previous.join("   "));
+        }
+        results
+    }
+
+    fn save(&self) {
+        let contents = serde_json::to_string_pretty(&self.history).unwrap();
+        if let Err(e) = fs::write(DAILY_PATH, contents) {
+            eprintln!("Unable to save daily runs to {}: {}", DAILY_PATH, e);
+        }
+    }
+}
+
+// Sets up the day's level on entry, ends the run when the level is cleared
+// and records the result once the run is over
+pub fn daily_system(
+    mode: Res<GameMode>,
+    mut daily: ResMut<Daily>,
+    mut levels: ResMut<Levels>,
+    mut lives: ResMut<Lives>,
+    mut current_state: ResMut<CurrentState>,
+    mut applied: Local<Option<GameMode>>,
+    mut state_reader: Local<EventReader<StateChanged>>,
+    state_events: Res<Events<StateChanged>>,
+    mut cleared_reader: Local<EventReader<LevelCleared>>,
+    cleared_events: Res<Events<LevelCleared>>,
+    mut run_reader: Local<EventReader<RunOver>>,
+    run_events: Res<Events<RunOver>>,
+    mut reset_events: ResMut<Events<ResetLevel>>,
+) {
+    let entered = *mode == GameMode::Daily && *applied != Some(GameMode::Daily);
+    *applied = Some(*mode);
+
+    let served = state_reader
+        .iter(&state_events)
+        .any(|transition| transition.from == GameState::ArenaStart && transition.to == GameState::Playing);
+    let cleared = cleared_reader.iter(&cleared_events).last().is_some();
+    let run_over = run_reader.iter(&run_events).last().cloned();
+    if *mode != GameMode::Daily {
+        return;
+    }
+
+    if served && !daily.scored {
+        daily.start_attempt();
+    }
+
+    if entered {
+        daily.refresh();
+        daily.results = None;
+        daily.scored = false;
+        daily.cleared = false;
+    } else if cleared && current_state.state == GameState::Playing {
+        // The daily challenge is a single level, so clearing it ends the run
+        daily.cleared = true;
+        current_state.set(GameState::GameOver);
+        return;
+    } else if let Some(run) = run_over {
+        daily.results = Some(daily.finish(run.score, run.seconds));
+        daily.cleared = false;
+    } else {
+        return;
+    }
+
+    lives.count[0] = daily.lives();
+    levels.daily = daily.level();
+    reset_events.send(ResetLevel);
+}
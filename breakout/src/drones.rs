@@ -0,0 +1,303 @@
+use bevy::prelude::*;
+use bevy_rapier3d::physics::RigidBodyHandleComponent;
+use bevy_rapier3d::rapier::dynamics::RigidBodySet;
+use serde::{Deserialize, Serialize};
+use crate::{BallEntity, BallLost, CurrentState, GameState, Paddle, Scoreboard, PADDLE_HALF_WIDTH, PADDLE_MAX_X};
+use crate::level::{Levels, ResetLevel};
+use crate::modes::GameMode;
+use crate::rng::Rng;
+use crate::scoring::{Combo, PointsAwarded, ScoringRules};
+
+// Drones aren't in the physics world; they're moved by hand and checked
+// against the ball and paddles as circles on the board
+const DRONE_RADIUS: f32 = 1.5;
+const BALL_RADIUS: f32 = 1.0;
+const PADDLE_HALF_DEPTH: f32 = 1.0;
+const DRONE_Y: f32 = 3.0;
+
+// A drone that gets past the bottom paddle leaves the field
+const EXIT_Z: f32 = -45.0;
+
+const DRIFT_SPEED: f32 = 4.0;
+const DRIFT_WOBBLE: f32 = 4.0;
+const ZIGZAG_DESCENT: f32 = 3.0;
+const ZIGZAG_SPEED: f32 = 12.0;
+const CHASE_DESCENT: f32 = 3.0;
+const CHASE_SPEED: f32 = 6.0;
+const ORBIT_DESCENT: f32 = 2.0;
+const ORBIT_RADIUS: f32 = 5.0;
+const ORBIT_SPEED: f32 = 10.0;
+
+fn default_max_alive() -> usize {
+    3
+}
+
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DroneBehavior {
+    // Floats down, wobbling side to side
+    Drift,
+    // Crosses the board from wall to wall on the way down
+    Zigzag,
+    // Heads for the nearest paddle
+    Chase,
+    // Loops round a point that sinks slowly down the board
+    Orbit,
+}
+
+impl DroneBehavior {
+    fn color(self) -> Color {
+        match self {
+            DroneBehavior::Drift => Color::rgb(0.2, 2.3, 0.4),
+            DroneBehavior::Zigzag => Color::rgb(2.3, 2.3, 0.2),
+            DroneBehavior::Chase => Color::rgb(2.3, 0.2, 0.2),
+            DroneBehavior::Orbit => Color::rgb(0.2, 1.2, 2.3),
+        }
+    }
+}
+
+// Where drones come from in a level file
+#[derive(Clone, Serialize, Deserialize)]
+pub struct DroneSpawner {
+    pub x: f32,
+    pub z: f32,
+    pub behavior: DroneBehavior,
+    // Seconds of play between drones
+    pub interval: f32,
+    // No more than this many of the spawner's drones are out at once
+    #[serde(default = "default_max_alive")]
+    pub max_alive: usize,
+}
+
+pub struct DroneDestroyed {
+    pub position: Vec3,
+    pub color: Color,
+}
+
+pub struct Drone {
+    behavior: DroneBehavior,
+    spawner: usize,
+    seconds: f32,
+    // Centre of the drone's pattern, which sinks down the board over time
+    anchor_x: f32,
+    anchor_z: f32,
+    x_velocity: f32,
+    position: Vec3,
+    // Left for drone_spawner_system to despawn
+    destroyed: bool,
+}
+
+impl Drone {
+    fn step(&mut self, dt: f32, paddle_x: f32) {
+        self.seconds += dt;
+        let (x, z) = match self.behavior {
+            DroneBehavior::Drift => {
+                self.anchor_z -= DRIFT_SPEED * dt;
+                (self.anchor_x + DRIFT_WOBBLE * (self.seconds * 2.0).sin(), self.anchor_z)
+            },
+            DroneBehavior::Zigzag => {
+                self.anchor_z -= ZIGZAG_DESCENT * dt;
+                self.anchor_x += self.x_velocity * dt;
+                if self.anchor_x.abs() > PADDLE_MAX_X {
+                    self.anchor_x = PADDLE_MAX_X * self.anchor_x.signum();
+                    self.x_velocity = -self.x_velocity;
+                }
+                (self.anchor_x, self.anchor_z)
+            },
+            DroneBehavior::Chase => {
+                self.anchor_z -= CHASE_DESCENT * dt;
+                let reach = CHASE_SPEED * dt;
+                self.anchor_x += f32::max(-reach, f32::min(reach, paddle_x - self.anchor_x));
+                (self.anchor_x, self.anchor_z)
+            },
+            DroneBehavior::Orbit => {
+                self.anchor_z -= ORBIT_DESCENT * dt;
+                let angle = self.seconds * ORBIT_SPEED / ORBIT_RADIUS;
+                (self.anchor_x + ORBIT_RADIUS * angle.cos(), self.anchor_z + ORBIT_RADIUS * angle.sin())
+            },
+        };
+        self.position = Vec3::new(x, DRONE_Y, z);
+    }
+
+    // Whether something `reach` across at the point touches the drone
+    pub fn touches(&self, point: Vec3, reach: f32) -> bool {
+        !self.destroyed && (self.position.x() - point.x()).hypot(self.position.z() - point.z()) < DRONE_RADIUS + reach
+    }
+
+    // Leaves the drone for drone_spawner_system to despawn
+    pub fn knock_out(&mut self) -> DroneDestroyed {
+        self.destroyed = true;
+        DroneDestroyed {
+            position: self.position,
+            color: self.behavior.color(),
+        }
+    }
+}
+
+pub struct DroneSpawning {
+    // Seconds since each of the level's spawners last sent out a drone
+    timers: Vec<f32>,
+    rng: Rng,
+}
+
+impl Default for DroneSpawning {
+    fn default() -> Self {
+        DroneSpawning {
+            timers: vec![],
+            rng: Rng::new(0),
+        }
+    }
+}
+
+// Moves the drones while the ball is in play. The ball knocks a drone out for
+// bonus points and bounces off it; a drone that reaches a paddle is destroyed
+// for nothing.
+pub fn drone_system(
+    time: Res<Time>,
+    current_state: Res<CurrentState>,
+    rules: Res<ScoringRules>,
+    ball_entity: Res<BallEntity>,
+    mut bodies: ResMut<RigidBodySet>,
+    mut scoreboard: ResMut<Scoreboard>,
+    combo: Res<Combo>,
+    mut points_events: ResMut<Events<PointsAwarded>>,
+    mut destroyed_events: ResMut<Events<DroneDestroyed>>,
+    mut drones: Query<(Mut<Drone>, Mut<Translation>)>,
+    mut paddles: Query<(&Paddle, &RigidBodyHandleComponent)>,
+    handles: Query<&RigidBodyHandleComponent>,
+) {
+    if current_state.state != GameState::Playing {
+        return;
+    }
+
+    let mut paddle_positions = vec![];
+    for (_paddle, handle) in &mut paddles.iter() {
+        if let Some(body) = bodies.get(handle.handle()) {
+            paddle_positions.push(body.position.translation);
+        }
+    }
+    let ball_handle = match handles.get::<RigidBodyHandleComponent>(ball_entity.0) {
+        Ok(handle) => handle.handle(),
+        Err(_) => return,
+    };
+
+    for (mut drone, mut translation) in &mut drones.iter() {
+        if drone.destroyed {
+            continue;
+        }
+
+        let nearest_paddle_x = paddle_positions
+            .iter()
+            .min_by(|a, b| {
+                let (da, db) = ((a.z - drone.anchor_z).abs(), (b.z - drone.anchor_z).abs());
+                da.partial_cmp(&db).unwrap()
+            })
+            .map_or(drone.anchor_x, |paddle| paddle.x);
+        drone.step(time.delta_seconds, nearest_paddle_x);
+        let position = drone.position;
+        translation.0 = position;
+
+        let reached_paddle = paddle_positions.iter().any(|paddle| {
+            (paddle.x - position.x()).abs() < PADDLE_HALF_WIDTH + DRONE_RADIUS
+                && (paddle.z - position.z()).abs() < PADDLE_HALF_DEPTH + DRONE_RADIUS
+        });
+        if reached_paddle {
+            destroyed_events.send(drone.knock_out());
+            continue;
+        }
+
+        let ball = bodies.get(ball_handle).unwrap().position.translation;
+        let (dx, dz) = (ball.x - position.x(), ball.z - position.z());
+        let distance = dx.hypot(dz);
+        if distance >= BALL_RADIUS + DRONE_RADIUS {
+            continue;
+        }
+
+        // Reflect the ball off the drone's surface when it's heading inwards
+        if distance > 0.0 {
+            let mut ball_body = bodies.get_mut(ball_handle).unwrap();
+            let (nx, nz) = (dx / distance, dz / distance);
+            let along = ball_body.linvel.x * nx + ball_body.linvel.z * nz;
+            if along < 0.0 {
+                ball_body.linvel.x -= 2.0 * along * nx;
+                ball_body.linvel.z -= 2.0 * along * nz;
+            }
+        }
+
+        let points = rules.drone_points * combo.multiplier;
+        scoreboard.score += points;
+        let last_touch = scoreboard.last_touch;
+        scoreboard.players[last_touch] += points;
+        points_events.send(PointsAwarded { points, position });
+        destroyed_events.send(drone.knock_out());
+    }
+}
+
+// Sends drones out from the level's spawners while the ball is in play and
+// clears them away when the ball is lost or the level starts over
+pub fn drone_spawner_system(
+    mut commands: Commands,
+    time: Res<Time>,
+    mode: Res<GameMode>,
+    levels: Res<Levels>,
+    current_state: Res<CurrentState>,
+    asset_server: Res<AssetServer>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut spawning: ResMut<DroneSpawning>,
+    mut lost_reader: Local<EventReader<BallLost>>,
+    lost_events: Res<Events<BallLost>>,
+    mut reset_reader: Local<EventReader<ResetLevel>>,
+    reset_events: Res<Events<ResetLevel>>,
+    mut drones: Query<(Entity, &Drone)>,
+) {
+    let lost = lost_reader.iter(&lost_events).last().is_some();
+    let reset = reset_reader.iter(&reset_events).last().is_some();
+    let spawners = &levels.for_mode(*mode).drones;
+
+    let mut alive = vec![0; spawners.len()];
+    for (entity, drone) in &mut drones.iter() {
+        if drone.destroyed || lost || reset || drone.position.z() < EXIT_Z {
+            commands.despawn(entity);
+        } else if drone.spawner < alive.len() {
+            alive[drone.spawner] += 1;
+        }
+    }
+
+    if reset || spawning.timers.len() != spawners.len() {
+        spawning.timers = vec![0.0; spawners.len()];
+    }
+    if current_state.state != GameState::Playing || lost || reset {
+        return;
+    }
+
+    for (index, spawner) in spawners.iter().enumerate() {
+        spawning.timers[index] += time.delta_seconds;
+        if spawning.timers[index] < spawner.interval || alive[index] >= spawner.max_alive {
+            continue;
+        }
+        spawning.timers[index] = 0.0;
+
+        let direction = if spawning.rng.chance(0.5) { 1.0 } else { -1.0 };
+        let position = Vec3::new(spawner.x, DRONE_Y, spawner.z);
+        commands
+            .spawn(PbrComponents {
+                mesh: asset_server
+                    .load("assets/blender/ball/export/ball.gltf")
+                    .unwrap(),
+                material: materials.add(spawner.behavior.color().into()),
+                translation: Translation(position),
+                scale: Scale(DRONE_RADIUS / BALL_RADIUS),
+                ..Default::default()
+            })
+            .with(Drone {
+                behavior: spawner.behavior,
+                spawner: index,
+                seconds: 0.0,
+                anchor_x: spawner.x,
+                anchor_z: spawner.z,
+                x_velocity: direction * ZIGZAG_SPEED,
+                position,
+                destroyed: false,
+            });
+    }
+}
@@ -0,0 +1,388 @@
+use bevy::prelude::*;
+use bevy::render::camera::Camera;
+use bevy::window::CursorMoved;
+use crate::{CurrentState, GameCamera, GameState, Infoboard, Lives, Scoreboard, MAX_PLAYERS, START_LIVES};
+use crate::attract::Attract;
+use crate::level::{BlockKind, BlockSpec, Level, Levels, ResetLevel, DEFAULT_BALL_SPEED};
+use crate::modes::GameMode;
+use crate::music::LEVEL_TRACKS;
+
+// Blocks snap to a 5 unit grid over the upper part of the board
+const GRID_CELL: f32 = 5.0;
+const GRID_MAX_X: f32 = 25.0;
+const GRID_MIN_Z: f32 = -10.0;
+const GRID_MAX_Z: f32 = 35.0;
+const BLOCK_Y: f32 = 3.0;
+
+const MIN_BALL_SPEED: f32 = 15.0;
+const MAX_BALL_SPEED: f32 = 60.0;
+const BALL_SPEED_STEP: f32 = 5.0;
+
+const MAX_NAME_LENGTH: usize = 24;
+
+const PALETTE: [[f32; 3]; 7] = [
+    [2.3, 2.3, 0.0],
+    [2.3, 0.4, 0.0],
+    [2.3, 1.2, 0.0],
+    [0.0, 2.3, 0.4],
+    [0.4, 1.2, 2.3],
+    [2.3, 0.4, 2.3],
+    [2.3, 2.3, 2.3],
+];
+
+pub struct Editor {
+    // Set while a level is being tried out from the editor; E goes back to it
+    pub playtesting: bool,
+    // Typing the level's name, which swallows every other key
+    pub naming: bool,
+    kind: BlockKind,
+    color: usize,
+    cursor_position: Option<Vec2>,
+    cell: Option<(f32, f32)>,
+    status: String,
+}
+
+impl Default for Editor {
+    fn default() -> Self {
+        Editor {
+            playtesting: false,
+            naming: false,
+            kind: BlockKind::Standard,
+            color: 0,
+            cursor_position: None,
+            cell: None,
+            status: String::new(),
+        }
+    }
+}
+
+impl Editor {
+    fn color(&self) -> Color {
+        let [r, g, b] = PALETTE[self.color];
+        Color::rgb(r, g, b)
+    }
+
+    fn banner(&self, levels: &Levels) -> String {
+        let level = levels.current();
+        let name = if self.naming { format!("{}_", level.name) } else { level.name.clone() };
+        // Edits to the other levels stay in memory until they're saved too
+        let others = levels.levels.iter().filter(|other| other.unsaved).count() - level.unsaved as usize;
+        let others = match others {
+            0 => String::new(),
+            1 => "   1 other level unsaved".to_string(),
+            n => format!("   {} other levels unsaved", n),
+        };
+        format!(
+            "EDITING {}{} ({}/{}){}\nBlock: {}   Music: {}   Ball speed: {}\n\
+             Left click place, right click remove, middle click recolor\n\
+             K kind, TAB color, F5 music, +/- speed, N name\n\
+             PGUP/PGDN level, INS new level, S save, T playtest, ESC exit\n{}",
+            name,
+            if level.unsaved { "*" } else { "" },
+            levels.current + 1,
+            levels.levels.len(),
+            others,
+            self.kind.name(),
+            level.music.as_ref().map_or("none", |music| music.as_str()),
+            level.ball_speed,
+            self.status,
+        )
+    }
+}
+
+// Marks the block that follows the mouse while editing
+pub struct EditorCursor;
+
+fn spawn_cursor(commands: &mut Commands, asset_server: &AssetServer, materials: &mut Assets<StandardMaterial>, color: Color) {
+    commands
+        .spawn(PbrComponents {
+            mesh: asset_server
+                .load("assets/blender/block/export/block.gltf")
+                .unwrap(),
+            material: materials.add(color.into()),
+            translation: Translation(Vec3::new(0.0, -100.0, 0.0)),
+            ..Default::default()
+        })
+        .with(EditorCursor);
+}
+
+// Enters and leaves the editor, and switches between editing and playtesting
+pub fn editor_toggle_system(
+    mut commands: Commands,
+    keyboard_input: Res<Input<KeyCode>>,
+    attract: Res<Attract>,
+    mode: Res<GameMode>,
+    asset_server: Res<AssetServer>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut editor: ResMut<Editor>,
+    mut current_state: ResMut<CurrentState>,
+    mut scoreboard: ResMut<Scoreboard>,
+    mut lives: ResMut<Lives>,
+    mut reset_events: ResMut<Events<ResetLevel>>,
+    mut cursors: Query<(Entity, &EditorCursor)>,
+) {
+    // Versus and endless play on fields of their own
+    if attract.active || !mode.uses_campaign() || editor.naming {
+        return;
+    }
+
+    let state = current_state.state;
+    if state == GameState::Editing {
+        let playtest = keyboard_input.just_pressed(KeyCode::T);
+        if playtest || keyboard_input.just_pressed(KeyCode::Escape) {
+            editor.playtesting = playtest;
+            scoreboard.reset();
+            lives.count = [START_LIVES; MAX_PLAYERS];
+            reset_events.send(ResetLevel);
+            current_state.set(GameState::ArenaStart);
+            for (entity, _cursor) in &mut cursors.iter() {
+                commands.despawn(entity);
+            }
+        }
+    } else if keyboard_input.just_pressed(KeyCode::E)
+        && (state == GameState::ArenaStart || (editor.playtesting && state == GameState::Paused))
+    {
+        editor.playtesting = false;
+        editor.status = String::new();
+        reset_events.send(ResetLevel);
+        current_state.set(GameState::Editing);
+        spawn_cursor(&mut commands, &asset_server, &mut materials, editor.color());
+    }
+}
+
+// Casts the mouse position onto the blocks' plane and snaps it to the grid
+pub fn editor_cursor_system(
+    windows: Res<Windows>,
+    mut cursor_reader: Local<EventReader<CursorMoved>>,
+    cursor_events: Res<Events<CursorMoved>>,
+    current_state: Res<CurrentState>,
+    mut editor: ResMut<Editor>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut cameras: Query<(&GameCamera, &Camera, &Transform)>,
+    mut cursors: Query<(&EditorCursor, &Handle<StandardMaterial>, Mut<Translation>)>,
+) {
+    if let Some(moved) = cursor_reader.iter(&cursor_events).last() {
+        editor.cursor_position = Some(moved.position);
+    }
+
+    if current_state.state != GameState::Editing {
+        return;
+    }
+
+    let window = match windows.get_primary() {
+        Some(window) => window,
+        None => return,
+    };
+
+    let mut view_projection = None;
+    for (_game_camera, camera, transform) in &mut cameras.iter() {
+        view_projection = Some(camera.projection_matrix * transform.value.inverse());
+    }
+
+    editor.cell = None;
+    if let (Some(view_projection), Some(position)) = (view_projection, editor.cursor_position) {
+        // Any two depths give a point on the ray through the cursor
+        let ndc_x = position.x() / window.width as f32 * 2.0 - 1.0;
+        let ndc_y = position.y() / window.height as f32 * 2.0 - 1.0;
+        let inverse = view_projection.inverse();
+        let unproject = |depth: f32| {
+            let point = inverse * Vec4::new(ndc_x, ndc_y, depth, 1.0);
+            point.truncate() / point.w()
+        };
+        let near = unproject(0.0);
+        let direction = unproject(1.0) - near;
+
+        if direction.y().abs() > std::f32::EPSILON {
+            let hit = near + direction * ((BLOCK_Y - near.y()) / direction.y());
+            let x = (hit.x() / GRID_CELL).round() * GRID_CELL;
+            let z = (hit.z() / GRID_CELL).round() * GRID_CELL;
+            if x.abs() <= GRID_MAX_X && z >= GRID_MIN_Z && z <= GRID_MAX_Z {
+                editor.cell = Some((x, z));
+            }
+        }
+    }
+
+    for (_cursor, material, mut translation) in &mut cursors.iter() {
+        // Parked under the board while the mouse is off the grid
+        translation.0 = match editor.cell {
+            Some((x, z)) => Vec3::new(x, BLOCK_Y, z),
+            None => Vec3::new(0.0, -100.0, 0.0),
+        };
+        if let Some(material) = materials.get_mut(material) {
+            material.albedo = editor.color();
+        }
+    }
+}
+
+fn name_char(key: KeyCode) -> Option<char> {
+    let letters = [
+        (KeyCode::A, 'a'), (KeyCode::B, 'b'), (KeyCode::C, 'c'), (KeyCode::D, 'd'), (KeyCode::E, 'e'),
+        (KeyCode::F, 'f'), (KeyCode::G, 'g'), (KeyCode::H, 'h'), (KeyCode::I, 'i'), (KeyCode::J, 'j'),
+        (KeyCode::K, 'k'), (KeyCode::L, 'l'), (KeyCode::M, 'm'), (KeyCode::N, 'n'), (KeyCode::O, 'o'),
+        (KeyCode::P, 'p'), (KeyCode::Q, 'q'), (KeyCode::R, 'r'), (KeyCode::S, 's'), (KeyCode::T, 't'),
+        (KeyCode::U, 'u'), (KeyCode::V, 'v'), (KeyCode::W, 'w'), (KeyCode::X, 'x'), (KeyCode::Y, 'y'),
+        (KeyCode::Z, 'z'), (KeyCode::Key0, '0'), (KeyCode::Key1, '1'), (KeyCode::Key2, '2'),
+        (KeyCode::Key3, '3'), (KeyCode::Key4, '4'), (KeyCode::Key5, '5'), (KeyCode::Key6, '6'),
+        (KeyCode::Key7, '7'), (KeyCode::Key8, '8'), (KeyCode::Key9, '9'), (KeyCode::Space, ' '),
+        (KeyCode::Minus, '-'),
+    ];
+    letters.iter().find(|(code, _)| *code == key).map(|(_, c)| *c)
+}
+
+// Every change goes straight into the current level and rebuilds the board
+pub fn editor_system(
+    keyboard_input: Res<Input<KeyCode>>,
+    mouse_input: Res<Input<MouseButton>>,
+    current_state: Res<CurrentState>,
+    mut editor: ResMut<Editor>,
+    mut levels: ResMut<Levels>,
+    mut reset_events: ResMut<Events<ResetLevel>>,
+    mut infoboard: Query<(&mut Text, &Infoboard)>,
+) {
+    if current_state.state != GameState::Editing {
+        return;
+    }
+
+    let current = levels.current;
+    let mut changed = false;
+
+    if editor.naming {
+        let level = &mut levels.levels[current];
+        let shift = keyboard_input.pressed(KeyCode::LShift) || keyboard_input.pressed(KeyCode::RShift);
+        for key in keyboard_input.get_just_pressed() {
+            level.unsaved = true;
+            match key {
+                KeyCode::Return | KeyCode::Escape => editor.naming = false,
+                KeyCode::Back => {
+                    level.name.pop();
+                },
+                _ => {
+                    if let Some(c) = name_char(*key) {
+                        if level.name.len() < MAX_NAME_LENGTH {
+                            level.name.push(if shift { c.to_ascii_uppercase() } else { c });
+                        }
+                    }
+                },
+            }
+        }
+        // An empty name would save to a file called ".json"
+        if !editor.naming && level.name.trim().is_empty() {
+            level.name = format!("Level {}", current + 1);
+        }
+    } else {
+        if keyboard_input.just_pressed(KeyCode::K) {
+            let kinds = BlockKind::all();
+            let index = kinds.iter().position(|kind| *kind == editor.kind).unwrap_or(0);
+            editor.kind = kinds[(index + 1) % kinds.len()];
+        }
+        if keyboard_input.just_pressed(KeyCode::Tab) {
+            editor.color = (editor.color + 1) % PALETTE.len();
+        }
+        if keyboard_input.just_pressed(KeyCode::N) {
+            editor.naming = true;
+            editor.status = "Type a name, ENTER to finish".to_string();
+        }
+
+        let level = &mut levels.levels[current];
+        if keyboard_input.just_pressed(KeyCode::F5) {
+            // Steps through the tracks and then no music at all
+            let index = level
+                .music
+                .as_ref()
+                .and_then(|music| LEVEL_TRACKS.iter().position(|track| track == music));
+            level.music = match index {
+                Some(index) if index + 1 < LEVEL_TRACKS.len() => Some(LEVEL_TRACKS[index + 1].to_string()),
+                Some(_) => None,
+                None => Some(LEVEL_TRACKS[0].to_string()),
+            };
+            level.unsaved = true;
+        }
+        if keyboard_input.just_pressed(KeyCode::Equals) {
+            level.ball_speed = f32::min(MAX_BALL_SPEED, level.ball_speed + BALL_SPEED_STEP);
+            level.unsaved = true;
+        }
+        if keyboard_input.just_pressed(KeyCode::Minus) {
+            level.ball_speed = f32::max(MIN_BALL_SPEED, level.ball_speed - BALL_SPEED_STEP);
+            level.unsaved = true;
+        }
+
+        if let Some((x, z)) = editor.cell {
+            let occupied = level
+                .blocks
+                .iter()
+                .position(|block| (block.x - x).abs() < GRID_CELL / 2.0 && (block.z - z).abs() < GRID_CELL / 2.0);
+
+            if mouse_input.just_pressed(MouseButton::Left) {
+                if let Some(index) = occupied {
+                    level.blocks.remove(index);
+                }
+                level.blocks.push(BlockSpec {
+                    x,
+                    z,
+                    color: editor.color(),
+                    kind: editor.kind,
+                    motion: None,
+                });
+                changed = true;
+            } else if mouse_input.just_pressed(MouseButton::Right) {
+                if let Some(index) = occupied {
+                    level.blocks.remove(index);
+                    changed = true;
+                }
+            } else if mouse_input.just_pressed(MouseButton::Middle) {
+                if let Some(index) = occupied {
+                    level.blocks[index].color = editor.color();
+                    changed = true;
+                }
+            }
+        }
+
+        if keyboard_input.just_pressed(KeyCode::S) {
+            editor.status = match level.save() {
+                Ok(path) => format!("Saved to {}", path),
+                Err(e) => format!("Save failed: {}", e),
+            };
+        }
+
+        let mut switched = false;
+        if keyboard_input.just_pressed(KeyCode::PageUp) {
+            levels.current = (current + levels.levels.len() - 1) % levels.levels.len();
+            switched = true;
+        }
+        if keyboard_input.just_pressed(KeyCode::PageDown) {
+            levels.advance();
+            switched = true;
+        }
+        if keyboard_input.just_pressed(KeyCode::Insert) {
+            let name = format!("Level {}", levels.levels.len() + 1);
+            levels.levels.push(Level {
+                name,
+                music: None,
+                ball_speed: DEFAULT_BALL_SPEED,
+                blocks: vec![],
+                drones: vec![],
+                boss: None,
+                path: None,
+                // Only on disk once it's saved
+                unsaved: true,
+            });
+            levels.current = levels.levels.len() - 1;
+            switched = true;
+        }
+        if switched {
+            editor.status = String::new();
+            changed = true;
+        }
+    }
+
+    if changed {
+        if levels.current == current {
+            levels.levels[current].unsaved = true;
+        }
+        reset_events.send(ResetLevel);
+    }
+
+    for (mut text, _infoboard) in &mut infoboard.iter() {
+        text.value = editor.banner(&levels);
+    }
+}
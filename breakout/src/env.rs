@@ -0,0 +1,283 @@
+use std::collections::HashMap;
+use std::time::Instant;
+use bevy::prelude::*;
+use bevy_rapier3d::na::Vector3;
+use bevy_rapier3d::physics::RigidBodyHandleComponent;
+use bevy_rapier3d::physics::RapierPhysicsPlugin;
+use bevy_rapier3d::physics::Gravity;
+use bevy_rapier3d::rapier::dynamics::RigidBodySet;
+use serde::{Deserialize, Serialize};
+use crate::{
+    Ball, BallEntity, BallLost, Block, BlockDestroyed, BodyHandleToEntity, Contacts, CurrentState,
+    GameState, Lives, PaddleInput, PlayerEntity, Scoreboard, StateChanged, MAX_PLAYERS,
+};
+use crate::juice::{self, BlockFlash};
+use crate::level::{Levels, ResetLevel};
+use crate::modes::{self, GameMode};
+use crate::rng::Rng;
+use crate::scoring::{self, Combo, LevelCleared, PointsAwarded, ScoringRules};
+use crate::settings::Settings;
+
+// The simulation always advances by one fixed 60Hz frame per step
+pub const STEP_SECONDS: f32 = 1.0 / 60.0;
+const MAX_EPISODE_STEPS: usize = 60 * 180;
+const BALL_LOST_REWARD: f32 = -1.0;
+
+// Occupancy grid covering the board in 5 unit cells
+pub const GRID_COLUMNS: usize = 12;
+pub const GRID_ROWS: usize = 8;
+const GRID_CELL: f32 = 5.0;
+const GRID_MIN_X: f32 = -30.0;
+const GRID_MIN_Z: f32 = 0.0;
+
+#[derive(Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    Stay,
+    Left,
+    Right,
+}
+
+impl Action {
+    pub fn from_index(index: usize) -> Option<Action> {
+        match index {
+            0 => Some(Action::Stay),
+            1 => Some(Action::Left),
+            2 => Some(Action::Right),
+            _ => None,
+        }
+    }
+
+    fn direction(self) -> f32 {
+        match self {
+            Action::Stay => 0.0,
+            Action::Left => 1.0,
+            Action::Right => -1.0,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct Observation {
+    pub ball_position: [f32; 3],
+    pub ball_velocity: [f32; 3],
+    pub paddle_x: f32,
+    // Row-major, GRID_ROWS x GRID_COLUMNS, row 0 nearest the paddle
+    pub blocks: Vec<bool>,
+}
+
+pub struct BreakoutEnv {
+    app: App,
+    steps: usize,
+    last_score: usize,
+}
+
+// The gameplay systems without any rendering, input or audio. Shared by the
+// environment and netplay, which both drive the app one fixed step at a time.
+pub fn headless_app(mode: GameMode, lives: usize) -> AppBuilder {
+    let mut builder = App::build();
+    builder
+        .add_resource(Time::default())
+        .add_resource(BodyHandleToEntity(HashMap::new()))
+        .add_resource(Scoreboard::default())
+        .add_resource(Lives { count: [lives; MAX_PLAYERS] })
+        .add_resource(CurrentState::new(GameState::ArenaStart))
+        .add_resource(PaddleInput { direction: [0.0; MAX_PLAYERS], serve: false })
+        .add_resource(Levels::builtin())
+        .add_resource(mode)
+        .add_resource(Settings::default())
+        .add_resource(BlockFlash::default())
+        .add_event::<ResetLevel>()
+        .add_event::<BallLost>()
+        .add_event::<Contacts>()
+        .add_event::<BlockDestroyed>()
+        .add_event::<StateChanged>()
+        .add_event::<PointsAwarded>()
+        .add_event::<LevelCleared>()
+        .add_resource(ScoringRules::default())
+        .add_resource(Combo::default())
+        .add_plugin(RapierPhysicsPlugin)
+        .add_startup_system(crate::setup_arena.system())
+        .add_startup_system(crate::setup_blocks.system())
+        .add_system(modes::mode_arena_system.system())
+        .add_system(crate::level::level_reset_system.system())
+        .add_system(crate::motion::moving_block_system.system())
+        .add_system(crate::level::fallen_block_system.system())
+        .add_system(crate::paddle_movement_system.system())
+        .add_system(crate::body_to_entity_system.system())
+        .add_system(crate::ball_movement_system.system())
+        .add_system(crate::contact_system.system())
+        .add_system(scoring::scoring_system.system())
+        .add_system(crate::state_change_system.system())
+        // Broken blocks aren't drawn here, so they go without a flash
+        .add_system(juice::flash_system.system())
+        .add_resource(Gravity(Vector3::new(0.0, -3.7279, 0.0)));
+    builder
+}
+
+// Builds the headless app and hands the environment to `f` once startup has
+// completed.
+pub fn run<F>(f: F)
+where
+    F: Fn(&mut BreakoutEnv) + 'static,
+{
+    // Episodes end on the first lost ball
+    headless_app(GameMode::Classic, 1)
+        .set_runner(move |app| {
+            let mut env = BreakoutEnv {
+                app,
+                steps: 0,
+                last_score: 0,
+            };
+            f(&mut env);
+        })
+        .run();
+}
+
+impl BreakoutEnv {
+    pub fn reset(&mut self, seed: u64) -> Observation {
+        let mut rng = Rng::new(seed);
+        let serve_x = if rng.chance(0.5) { -1.0 } else { 1.0 };
+
+        {
+            let resources = &mut self.app.resources;
+            let mut levels = resources.get_mut::<Levels>().unwrap();
+            // Drones and bosses run on systems the headless app leaves out
+            let playable: Vec<usize> = (0..levels.levels.len())
+                .filter(|index| levels.levels[*index].drones.is_empty() && levels.levels[*index].boss.is_none())
+                .collect();
+            levels.current = playable[rng.below(playable.len())];
+        }
+        self.app.resources.get_mut::<Scoreboard>().unwrap().reset();
+        self.app.resources.get_mut::<Lives>().unwrap().count = [1; MAX_PLAYERS];
+        self.app.resources.get_mut::<CurrentState>().unwrap().set(GameState::ArenaStart);
+        self.app.resources.get_mut::<Events<ResetLevel>>().unwrap().send(ResetLevel);
+
+        let ball_entity = self.app.resources.get::<BallEntity>().unwrap().0;
+        if let Ok(mut ball) = self.app.world.get_mut::<Ball>(ball_entity) {
+            ball.velocity = Vec3::new(serve_x, 0.0, -1.0).normalize();
+        }
+        if let Ok(ball_handle) = self.app.world.get::<RigidBodyHandleComponent>(ball_entity) {
+            let mut bodies = self.app.resources.get_mut::<RigidBodySet>().unwrap();
+            let mut body = bodies.get_mut(ball_handle.handle()).unwrap();
+            body.linvel = Vector3::zeros();
+            body.angvel = Vector3::zeros();
+        }
+
+        // One frame to rebuild the blocks and serve, one for the commands to land
+        self.set_input(0.0, true);
+        self.tick();
+        self.set_input(0.0, false);
+        self.tick();
+
+        self.steps = 0;
+        self.last_score = 0;
+        self.observe()
+    }
+
+    pub fn step(&mut self, action: Action) -> (Observation, f32, bool) {
+        self.set_input(action.direction(), false);
+        self.tick();
+        self.steps += 1;
+
+        let score = self.app.resources.get::<Scoreboard>().unwrap().score;
+        let ball_lost = match self.app.resources.get::<CurrentState>().unwrap().state {
+            GameState::GameOver => true,
+            _ => false,
+        };
+
+        let mut reward = score.saturating_sub(self.last_score) as f32;
+        self.last_score = score;
+        if ball_lost {
+            reward += BALL_LOST_REWARD;
+        }
+
+        let observation = self.observe();
+        // Unbreakable blocks stay behind on a cleared level
+        let cleared = self.app.world.query::<&Block>().iter().all(|block| !block.kind.destructible());
+        let done = ball_lost || cleared || self.steps >= MAX_EPISODE_STEPS;
+        (observation, reward, done)
+    }
+
+    pub fn observe(&mut self) -> Observation {
+        let bodies = self.app.resources.get::<RigidBodySet>().unwrap();
+        let ball_entity = self.app.resources.get::<BallEntity>().unwrap().0;
+        let player_entity = self.app.resources.get::<PlayerEntity>().unwrap().0;
+
+        let mut ball_position = [0.0; 3];
+        let mut ball_velocity = [0.0; 3];
+        if let Ok(handle) = self.app.world.get::<RigidBodyHandleComponent>(ball_entity) {
+            let body = bodies.get(handle.handle()).unwrap();
+            let translation = body.position.translation;
+            ball_position = [translation.x, translation.y, translation.z];
+            ball_velocity = [body.linvel.x, body.linvel.y, body.linvel.z];
+        }
+
+        let mut paddle_x = 0.0;
+        if let Ok(handle) = self.app.world.get::<RigidBodyHandleComponent>(player_entity) {
+            paddle_x = bodies.get(handle.handle()).unwrap().position.translation.x;
+        }
+
+        let mut blocks = vec![false; GRID_COLUMNS * GRID_ROWS];
+        for (_block, handle) in self.app.world.query::<(&Block, &RigidBodyHandleComponent)>().iter() {
+            let translation = bodies.get(handle.handle()).unwrap().position.translation;
+            let column = ((translation.x - GRID_MIN_X) / GRID_CELL).floor();
+            let row = ((translation.z - GRID_MIN_Z) / GRID_CELL).floor();
+            if column >= 0.0 && row >= 0.0 && (column as usize) < GRID_COLUMNS && (row as usize) < GRID_ROWS {
+                blocks[row as usize * GRID_COLUMNS + column as usize] = true;
+            }
+        }
+
+        Observation {
+            ball_position,
+            ball_velocity,
+            paddle_x,
+            blocks,
+        }
+    }
+
+    fn set_input(&mut self, direction: f32, serve: bool) {
+        let mut paddle_input = self.app.resources.get_mut::<PaddleInput>().unwrap();
+        paddle_input.direction[0] = direction;
+        paddle_input.serve = serve;
+    }
+
+    fn tick(&mut self) {
+        self.app.resources.get_mut::<Time>().unwrap().delta_seconds = STEP_SECONDS;
+        self.app.update();
+    }
+}
+
+// Plays random episodes as fast as possible; handy for checking throughput
+pub fn run_benchmark(episodes: usize) {
+    run(move |env| {
+        let mut rng = Rng::new(0);
+        let mut total_reward = 0.0;
+        let mut total_steps = 0;
+        let start = Instant::now();
+
+        for episode in 0..episodes {
+            env.reset(episode as u64);
+            loop {
+                let action = Action::from_index(rng.below(3)).unwrap();
+                let (_observation, reward, done) = env.step(action);
+                total_reward += reward;
+                total_steps += 1;
+                if done {
+                    break;
+                }
+            }
+        }
+
+        let seconds = start.elapsed().as_secs_f32();
+        println!(
+            "{} episodes, {} steps in {:.2}s ({:.1} episodes/s, {:.0} steps/s), mean reward {:.2}",
+            episodes,
+            total_steps,
+            seconds,
+            episodes as f32 / seconds,
+            total_steps as f32 / seconds,
+            total_reward / episodes as f32,
+        );
+    });
+}
@@ -0,0 +1,369 @@
+use std::collections::VecDeque;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+use bevy::prelude::*;
+use crate::{CurrentState, GameState, StateChanged};
+use crate::drones::{DroneBehavior, DroneSpawner};
+use crate::level::{BlockKind, BlockSpec, Level, Levels, ResetLevel, DEFAULT_BALL_SPEED};
+use crate::modes::GameMode;
+use crate::music::LEVEL_TRACKS;
+use crate::rng::Rng;
+use crate::scoring::LevelCleared;
+
+// The same 6 by 6 field the built-in levels use, row 0 nearest the paddle
+const COLUMNS: usize = 6;
+const ROWS: usize = 6;
+const FIRST_X: f32 = -25.0;
+const FIRST_Z: f32 = 5.0;
+const COLUMN_SPACING: f32 = 10.0;
+const ROW_SPACING: f32 = 5.0;
+
+// Levels get denser, tougher and faster over this many stages
+const STAGES_TO_FULL_DIFFICULTY: f32 = 10.0;
+const MAX_EXTRA_BALL_SPEED: f32 = 10.0;
+
+const BLOCK_BRIGHTNESS: f32 = 2.3;
+
+// Drones come in just under the top wall, more often the later the stage
+const DRONE_Z: f32 = 36.0;
+const MAX_DRONE_CHANCE: f32 = 0.6;
+
+type Grid = [[Option<BlockKind>; COLUMNS]; ROWS];
+
+#[derive(Clone, Copy)]
+enum Pattern {
+    Rows,
+    Pyramid,
+    Noise,
+}
+
+fn fill(pattern: Pattern, difficulty: f32, rng: &mut Rng) -> Grid {
+    let mut grid = [[None; COLUMNS]; ROWS];
+    match pattern {
+        // Whole rows with the odd gap
+        Pattern::Rows => {
+            for row in 0..ROWS {
+                if rng.chance(0.5 + 0.3 * difficulty) {
+                    for column in 0..COLUMNS {
+                        if rng.chance(0.85) {
+                            grid[row][column] = Some(BlockKind::Standard);
+                        }
+                    }
+                }
+            }
+        },
+        // Narrowing by a block on each side every two rows, upside down half the time
+        Pattern::Pyramid => {
+            let inverted = rng.chance(0.5);
+            for row in 0..ROWS {
+                let layer = if inverted { (ROWS - 1 - row) / 2 } else { row / 2 };
+                for column in layer..(COLUMNS - layer) {
+                    grid[row][column] = Some(BlockKind::Standard);
+                }
+            }
+        },
+        // Value noise on a coarse lattice, thresholded by the density
+        Pattern::Noise => {
+            let mut lattice = [[0.0; 3]; 3];
+            for point in lattice.iter_mut().flat_map(|row| row.iter_mut()) {
+                *point = rng.next_f32();
+            }
+            let density = 0.35 + 0.35 * difficulty;
+            for row in 0..ROWS {
+                for column in 0..COLUMNS {
+                    let u = column as f32 / (COLUMNS - 1) as f32 * 2.0;
+                    let v = row as f32 / (ROWS - 1) as f32 * 2.0;
+                    let (u0, v0) = (f32::min(u.floor(), 1.0), f32::min(v.floor(), 1.0));
+                    let (tu, tv) = (u - u0, v - v0);
+                    let (i, j) = (u0 as usize, v0 as usize);
+                    let top = lattice[j][i] * (1.0 - tu) + lattice[j][i + 1] * tu;
+                    let bottom = lattice[j + 1][i] * (1.0 - tu) + lattice[j + 1][i + 1] * tu;
+                    let value = top * (1.0 - tv) + bottom * tv + rng.range(-0.1, 0.1);
+                    if value < density {
+                        grid[row][column] = Some(BlockKind::Standard);
+                    }
+                }
+            }
+        },
+    }
+    grid
+}
+
+// Cells the ball can get to from below without passing an unbreakable block.
+// Destructible blocks count as open since the ball breaks its way through.
+fn reachable(grid: &Grid) -> [[bool; COLUMNS]; ROWS] {
+    let mut reached = [[false; COLUMNS]; ROWS];
+    let mut queue = VecDeque::new();
+    for column in 0..COLUMNS {
+        if grid[0][column] != Some(BlockKind::Unbreakable) {
+            reached[0][column] = true;
+            queue.push_back((0, column));
+        }
+    }
+
+    while let Some((row, column)) = queue.pop_front() {
+        let mut neighbours = vec![];
+        if row > 0 {
+            neighbours.push((row - 1, column));
+        }
+        if row + 1 < ROWS {
+            neighbours.push((row + 1, column));
+        }
+        if column > 0 {
+            neighbours.push((row, column - 1));
+        }
+        if column + 1 < COLUMNS {
+            neighbours.push((row, column + 1));
+        }
+        for (next_row, next_column) in neighbours {
+            if !reached[next_row][next_column] && grid[next_row][next_column] != Some(BlockKind::Unbreakable) {
+                reached[next_row][next_column] = true;
+                queue.push_back((next_row, next_column));
+            }
+        }
+    }
+    reached
+}
+
+// Unbreakable blocks walling off a destructible one are weakened to tough
+// blocks all the way down its column, which always opens a way in from below
+fn make_reachable(grid: &mut Grid) {
+    let reached = reachable(grid);
+    for row in 0..ROWS {
+        for column in 0..COLUMNS {
+            let destructible = grid[row][column].map_or(false, |kind| kind.destructible());
+            if destructible && !reached[row][column] {
+                for below in 0..row {
+                    if grid[below][column] == Some(BlockKind::Unbreakable) {
+                        grid[below][column] = Some(BlockKind::Tough);
+                    }
+                }
+            }
+        }
+    }
+}
+
+pub fn hsv(hue: f32, saturation: f32, value: f32) -> Color {
+    let h = (hue.rem_euclid(1.0)) * 6.0;
+    let c = value * saturation;
+    let x = c * (1.0 - ((h % 2.0) - 1.0).abs());
+    let (r, g, b) = match h as usize {
+        0 => (c, x, 0.0),
+        1 => (x, c, 0.0),
+        2 => (0.0, c, x),
+        3 => (0.0, x, c),
+        4 => (x, 0.0, c),
+        _ => (c, 0.0, x),
+    };
+    let m = value - c;
+    Color::rgb(r + m, g + m, b + m)
+}
+
+// The same seed and stage always give the same level
+pub fn generate(seed: u64, stage: usize) -> Level {
+    let mut rng = Rng::new(seed);
+    let difficulty = f32::min(1.0, stage as f32 / STAGES_TO_FULL_DIFFICULTY);
+
+    let pattern = [Pattern::Rows, Pattern::Pyramid, Pattern::Noise][rng.below(3)];
+    let symmetric = rng.chance(0.6);
+    let mut grid = fill(pattern, difficulty, &mut rng);
+
+    for row in grid.iter_mut() {
+        for cell in row.iter_mut().filter(|cell| cell.is_some()) {
+            if rng.chance(0.04 + 0.08 * difficulty) {
+                *cell = Some(BlockKind::Unbreakable);
+            } else if rng.chance(0.1 + 0.3 * difficulty) {
+                *cell = Some(BlockKind::Tough);
+            }
+        }
+    }
+
+    if symmetric {
+        for row in grid.iter_mut() {
+            for column in (COLUMNS / 2)..COLUMNS {
+                row[column] = row[COLUMNS - 1 - column];
+            }
+        }
+    }
+
+    make_reachable(&mut grid);
+
+    // A level with nothing to break could never be cleared
+    let destructible = grid.iter().flat_map(|row| row.iter()).any(|cell| cell.map_or(false, |kind| kind.destructible()));
+    if !destructible {
+        grid[0][COLUMNS / 2] = Some(BlockKind::Standard);
+    }
+
+    let hue = rng.next_f32();
+    let hue_step = rng.range(-0.08, 0.08);
+    let mut blocks = vec![];
+    for (row, cells) in grid.iter().enumerate() {
+        let row_color = hsv(hue + hue_step * row as f32, 0.85, BLOCK_BRIGHTNESS);
+        for (column, cell) in cells.iter().enumerate() {
+            if let Some(kind) = cell {
+                let color = match kind {
+                    BlockKind::Standard | BlockKind::Physics => row_color,
+                    BlockKind::Tough => hsv(hue + hue_step * row as f32, 0.4, BLOCK_BRIGHTNESS),
+                    BlockKind::Unbreakable => Color::rgb(0.6, 0.6, 0.6),
+                };
+                blocks.push(BlockSpec {
+                    x: FIRST_X + column as f32 * COLUMN_SPACING,
+                    z: FIRST_Z + row as f32 * ROW_SPACING,
+                    color,
+                    kind: *kind,
+                    motion: None,
+                });
+            }
+        }
+    }
+
+    let music = LEVEL_TRACKS[rng.below(LEVEL_TRACKS.len())].to_string();
+
+    let mut drones = vec![];
+    if rng.chance(MAX_DRONE_CHANCE * difficulty) {
+        let behaviors = [DroneBehavior::Drift, DroneBehavior::Zigzag, DroneBehavior::Chase, DroneBehavior::Orbit];
+        drones.push(DroneSpawner {
+            x: rng.range(-20.0, 20.0),
+            z: DRONE_Z,
+            behavior: behaviors[rng.below(behaviors.len())],
+            interval: rng.range(10.0, 15.0) - 5.0 * difficulty,
+            max_alive: 1 + (2.0 * difficulty) as usize,
+        });
+    }
+
+    Level {
+        name: format!("Seed {}", seed),
+        music: Some(music),
+        ball_speed: (DEFAULT_BALL_SPEED + MAX_EXTRA_BALL_SPEED * difficulty).round(),
+        blocks,
+        drones,
+        boss: None,
+        path: None,
+        unsaved: false,
+    }
+}
+
+pub fn seed_from_clock() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
+
+// Writes `count` levels from consecutive seeds, ramping up through the stages
+// like endless mode does, for picking through and copying into assets/levels
+pub fn export(seed: u64, count: usize, directory: &str) {
+    for stage in 0..count {
+        let level = generate(seed.wrapping_add(stage as u64), stage);
+        let path = Path::new(directory).join(level.file_name());
+        match level.save_to(&path) {
+            Ok(()) => println!("{}: {} blocks", path.display(), level.blocks.len()),
+            Err(e) => eprintln!("Couldn't write {}: {}", path.display(), e),
+        }
+    }
+}
+
+pub struct Endless {
+    pub seed: u64,
+    pub stage: usize,
+}
+
+impl Endless {
+    pub fn new(seed: u64) -> Self {
+        Endless { seed, stage: 0 }
+    }
+
+    pub fn level(&self) -> Level {
+        let mut level = generate(self.seed.wrapping_add(self.stage as u64), self.stage);
+        level.name = format!("Endless {} (seed {})", self.stage + 1, self.seed);
+        level
+    }
+}
+
+// Endless mode starts over from the first stage on entry and after a game
+// over, and moves on to a freshly generated level whenever one is cleared
+pub fn endless_system(
+    mode: Res<GameMode>,
+    mut endless: ResMut<Endless>,
+    mut levels: ResMut<Levels>,
+    mut current_state: ResMut<CurrentState>,
+    mut applied: Local<Option<GameMode>>,
+    mut state_reader: Local<EventReader<StateChanged>>,
+    state_events: Res<Events<StateChanged>>,
+    mut cleared_reader: Local<EventReader<LevelCleared>>,
+    cleared_events: Res<Events<LevelCleared>>,
+    mut reset_events: ResMut<Events<ResetLevel>>,
+) {
+    let entered = *mode == GameMode::Endless && *applied != Some(GameMode::Endless);
+    *applied = Some(*mode);
+
+    let game_over = state_reader.iter(&state_events).any(|transition| transition.to == GameState::GameOver);
+    let cleared = cleared_reader.iter(&cleared_events).last().is_some();
+    if *mode != GameMode::Endless {
+        return;
+    }
+
+    if entered || game_over {
+        endless.stage = 0;
+    } else if cleared {
+        endless.stage += 1;
+        current_state.set(GameState::ArenaStart);
+    } else {
+        return;
+    }
+
+    levels.endless = endless.level();
+    reset_events.send(ResetLevel);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Rebuilds the grid from the finished level, so the check also covers
+    // everything generate does after make_reachable
+    fn grid_of(level: &Level) -> Grid {
+        let mut grid = [[None; COLUMNS]; ROWS];
+        for block in level.blocks.iter() {
+            let column = ((block.x - FIRST_X) / COLUMN_SPACING).round() as usize;
+            let row = ((block.z - FIRST_Z) / ROW_SPACING).round() as usize;
+            assert!(grid[row][column].is_none(), "two blocks in row {} column {}", row, column);
+            grid[row][column] = Some(block.kind);
+        }
+        grid
+    }
+
+    #[test]
+    fn every_destructible_block_is_reachable() {
+        for seed in 0..30 {
+            for stage in 0..15 {
+                let level = generate(seed * 7919, stage);
+                let grid = grid_of(&level);
+                let reached = reachable(&grid);
+                for row in 0..ROWS {
+                    for column in 0..COLUMNS {
+                        let destructible = grid[row][column].map_or(false, |kind| kind.destructible());
+                        assert!(
+                            !destructible || reached[row][column],
+                            "seed {} stage {}: row {} column {} is walled off",
+                            seed * 7919,
+                            stage,
+                            row,
+                            column,
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn every_level_has_something_to_break() {
+        for seed in 0..30 {
+            for stage in 0..15 {
+                let level = generate(seed * 7919, stage);
+                assert!(level.blocks.iter().any(|block| block.kind.destructible()));
+            }
+        }
+    }
+}
@@ -0,0 +1,64 @@
+use std::fs;
+
+const MAX_ENTRIES: usize = 10;
+
+pub struct HighScores {
+    path: String,
+    pub scores: Vec<usize>,
+}
+
+impl HighScores {
+    pub fn load(path: &str) -> Self {
+        let scores = match fs::read_to_string(path) {
+            Ok(contents) => contents
+                .lines()
+                .filter_map(|line| line.trim().parse::<usize>().ok())
+                .collect(),
+            Err(_) => vec![],
+        };
+
+        let mut high_scores = HighScores {
+            path: path.to_string(),
+            scores,
+        };
+        high_scores.scores.sort_by(|a, b| b.cmp(a));
+        high_scores.scores.truncate(MAX_ENTRIES);
+        high_scores
+    }
+
+    pub fn best(&self) -> usize {
+        self.scores.first().cloned().unwrap_or(0)
+    }
+
+    // Returns true when the score made it onto the table
+    pub fn record(&mut self, score: usize) -> bool {
+        if score == 0 {
+            return false;
+        }
+
+        let position = self.scores.iter().position(|s| score > *s).unwrap_or(self.scores.len());
+        if position >= MAX_ENTRIES {
+            return false;
+        }
+
+        self.scores.insert(position, score);
+        self.scores.truncate(MAX_ENTRIES);
+        self.save();
+        true
+    }
+
+    pub fn table(&self) -> String {
+        let mut table = "HIGH SCORES\n".to_string();
+        for (rank, score) in self.scores.iter().enumerate() {
+            table.push_str(&format!("{:>2}. {:>6}\n", rank + 1, score));
+        }
+        table
+    }
+
+    fn save(&self) {
+        let contents: Vec<String> = self.scores.iter().map(|s| s.to_string()).collect();
+        if let Err(e) = fs::write(&self.path, contents.join("\n")) {
+            eprintln!("Unable to save high scores to {}: {}", self.path, e);
+        }
+    }
+}
@@ -0,0 +1,261 @@
+use bevy::prelude::*;
+use crate::{Lives, RunTimer, Scoreboard};
+use crate::highscores::HighScores;
+use crate::level::Levels;
+use crate::modes::GameMode;
+use crate::powerups::ActivePowerUps;
+use crate::scoring::Combo;
+use crate::survival::SurvivalHighScores;
+use crate::time_attack::{self, TimeAttack};
+use crate::window::ScaledText;
+
+const LIFE_ICONS: usize = 5;
+const POWER_UP_SLOTS: usize = 3;
+const HUD_FONT_SIZE: f32 = 32.0;
+
+pub struct HudScore;
+
+pub struct HudHighScore;
+
+pub struct HudLevel;
+
+pub struct HudTimer;
+
+pub struct HudCombo;
+
+pub struct LifeIcon(usize);
+
+pub struct PowerUpSlot(usize);
+
+pub struct HudMaterials {
+    life_full: Handle<ColorMaterial>,
+    life_empty: Handle<ColorMaterial>,
+    hidden: Handle<ColorMaterial>,
+}
+
+fn hud_text(font: Handle<Font>, font_size: f32) -> TextComponents {
+    TextComponents {
+        text: Text {
+            font,
+            value: "".to_string(),
+            style: TextStyle {
+                color: Color::rgb(0.2, 0.2, 0.8),
+                font_size,
+            },
+        },
+        style: Style {
+            margin: Rect::all(Val::Px(8.0)),
+            ..Default::default()
+        },
+        ..Default::default()
+    }
+}
+
+pub fn setup_hud(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    let font = asset_server.load("assets/fonts/FiraSans-Bold.ttf").unwrap();
+    let transparent = materials.add(Color::rgba(0.0, 0.0, 0.0, 0.0).into());
+    let hud_materials = HudMaterials {
+        life_full: materials.add(Color::rgb(0.7, 0.0, 0.0).into()),
+        life_empty: materials.add(Color::rgba(0.7, 0.0, 0.0, 0.15).into()),
+        hidden: transparent,
+    };
+
+    commands
+        // - Top Bar -
+        .spawn(NodeComponents {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    top: Val::Px(0.0),
+                    left: Val::Px(0.0),
+                    ..Default::default()
+                },
+                size: Size::new(Val::Percent(100.0), Val::Auto),
+                flex_direction: FlexDirection::Row,
+                justify_content: JustifyContent::SpaceBetween,
+                align_items: AlignItems::Center,
+                padding: Rect::all(Val::Px(8.0)),
+                ..Default::default()
+            },
+            material: transparent,
+            ..Default::default()
+        })
+        .with_children(|bar| {
+            bar.spawn(hud_text(font, HUD_FONT_SIZE))
+                .with(HudScore)
+                .with(ScaledText { font_size: HUD_FONT_SIZE })
+                .spawn(hud_text(font, HUD_FONT_SIZE))
+                .with(HudHighScore)
+                .with(ScaledText { font_size: HUD_FONT_SIZE })
+                .spawn(hud_text(font, HUD_FONT_SIZE))
+                .with(HudLevel)
+                .with(ScaledText { font_size: HUD_FONT_SIZE })
+                .spawn(hud_text(font, HUD_FONT_SIZE))
+                .with(HudTimer)
+                .with(ScaledText { font_size: HUD_FONT_SIZE })
+                .spawn(hud_text(font, HUD_FONT_SIZE))
+                .with(HudCombo)
+                .with(ScaledText { font_size: HUD_FONT_SIZE })
+
+                // - Lives -
+                .spawn(NodeComponents {
+                    style: Style {
+                        flex_direction: FlexDirection::Row,
+                        margin: Rect::all(Val::Px(8.0)),
+                        ..Default::default()
+                    },
+                    material: transparent,
+                    ..Default::default()
+                })
+                .with_children(|icons| {
+                    for index in 0..LIFE_ICONS {
+                        icons
+                            .spawn(NodeComponents {
+                                style: Style {
+                                    size: Size::new(Val::Px(24.0), Val::Px(24.0)),
+                                    margin: Rect::all(Val::Px(4.0)),
+                                    ..Default::default()
+                                },
+                                material: hud_materials.life_empty,
+                                ..Default::default()
+                            })
+                            .with(LifeIcon(index));
+                    }
+                });
+        })
+
+        // - Power-ups -
+        .spawn(NodeComponents {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    bottom: Val::Px(0.0),
+                    right: Val::Px(0.0),
+                    ..Default::default()
+                },
+                flex_direction: FlexDirection::Column,
+                padding: Rect::all(Val::Px(8.0)),
+                ..Default::default()
+            },
+            material: transparent,
+            ..Default::default()
+        })
+        .with_children(|column| {
+            for index in 0..POWER_UP_SLOTS {
+                column
+                    .spawn(hud_text(font, HUD_FONT_SIZE * 0.75))
+                    .with(PowerUpSlot(index))
+                    .with(ScaledText { font_size: HUD_FONT_SIZE * 0.75 });
+            }
+        });
+
+    commands.insert_resource(hud_materials);
+}
+
+pub fn hud_system(
+    scoreboard: Res<Scoreboard>,
+    high_scores: Res<HighScores>,
+    levels: Res<Levels>,
+    mode: Res<GameMode>,
+    survival_high_scores: Res<SurvivalHighScores>,
+    lives: Res<Lives>,
+    power_ups: Res<ActivePowerUps>,
+    combo: Res<Combo>,
+    hud_materials: Res<HudMaterials>,
+    mut score_text: Query<(Mut<Text>, &HudScore)>,
+    mut high_score_text: Query<(Mut<Text>, &HudHighScore)>,
+    mut level_text: Query<(Mut<Text>, &HudLevel)>,
+    mut combo_text: Query<(Mut<Text>, &HudCombo)>,
+    mut power_up_text: Query<(Mut<Text>, &PowerUpSlot)>,
+    mut life_icons: Query<(Mut<Handle<ColorMaterial>>, &LifeIcon)>,
+) {
+    for (mut text, _) in &mut score_text.iter() {
+        text.value = match *mode {
+            // Both players share the bar, each with their own lives
+            GameMode::Versus => format!(
+                "P1 {} ({} lives)   P2 {} ({} lives)",
+                scoreboard.players[0],
+                lives.count[0],
+                scoreboard.players[1],
+                lives.count[1],
+            ),
+            // One shared pool of lives, but each player's share of the score
+            GameMode::Coop => format!(
+                "Score {} (P1 {} / P2 {})",
+                scoreboard.score,
+                scoreboard.players[0],
+                scoreboard.players[1],
+            ),
+            _ => format!("Score {}", scoreboard.score),
+        };
+    }
+
+    for (mut text, _) in &mut high_score_text.iter() {
+        let best = if *mode == GameMode::Survival {
+            survival_high_scores.0.best()
+        } else {
+            high_scores.best()
+        };
+        text.value = format!("Best {}", usize::max(best, scoreboard.score));
+    }
+
+    for (mut text, _) in &mut level_text.iter() {
+        text.value = match *mode {
+            GameMode::Versus => levels.versus.name.clone(),
+            GameMode::Endless => levels.endless.name.clone(),
+            GameMode::Daily => levels.daily.name.clone(),
+            GameMode::Survival => levels.survival.name.clone(),
+            _ => format!("Level {} - {}", levels.current + 1, levels.current().name),
+        };
+    }
+
+    for (mut text, _) in &mut combo_text.iter() {
+        text.value = if combo.multiplier > 1 {
+            format!("Combo x{}", combo.multiplier)
+        } else {
+            "".to_string()
+        };
+    }
+
+    for (mut text, slot) in &mut power_up_text.iter() {
+        text.value = match power_ups.active.get(slot.0) {
+            Some(power_up) => format!("{} {:.1}s", power_up.name, power_up.seconds_left),
+            None => "".to_string(),
+        };
+    }
+
+    for (mut material, icon) in &mut life_icons.iter() {
+        *material = if *mode == GameMode::Versus || !mode.has_lives() {
+            hud_materials.hidden
+        } else if icon.0 < lives.count[0] {
+            hud_materials.life_full
+        } else {
+            hud_materials.life_empty
+        };
+    }
+}
+
+// Time attack swaps the run timer for a stopwatch with the penalties shown
+pub fn hud_timer_system(
+    mode: Res<GameMode>,
+    run_timer: Res<RunTimer>,
+    time_attack: Res<TimeAttack>,
+    mut timer_text: Query<(Mut<Text>, &HudTimer)>,
+) {
+    for (mut text, _) in &mut timer_text.iter() {
+        text.value = if *mode == GameMode::TimeAttack {
+            let mut stopwatch = time_attack::format_time(time_attack.total_seconds());
+            if time_attack.penalty_seconds > 0.0 {
+                stopwatch += &format!(" (+{}s)", time_attack.penalty_seconds);
+            }
+            stopwatch
+        } else {
+            let seconds = run_timer.seconds as usize;
+            format!("{:02}:{:02}", seconds / 60, seconds % 60)
+        };
+    }
+}
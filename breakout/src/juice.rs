@@ -0,0 +1,216 @@
+use bevy::prelude::*;
+use bevy_rapier3d::rapier::dynamics::IntegrationParameters;
+use crate::{BallLost, BlockDestroyed, Contacts, CurrentState, GameCamera, GameState};
+use crate::rng::Rng;
+use crate::settings::Settings;
+
+const MAX_SHAKE_OFFSET: f32 = 1.5;
+const SHAKE_DECAY: f32 = 1.5;
+const BLOCK_TRAUMA: f32 = 0.25;
+const BALL_LOST_TRAUMA: f32 = 0.8;
+
+// A hit that takes out several blocks in the same frame counts as big
+const BIG_HIT_BLOCKS: usize = 2;
+const HIT_STOP_FRAMES: u32 = 4;
+
+const SQUASH_SECONDS: f32 = 0.15;
+const FLASH_SECONDS: f32 = 0.08;
+
+pub struct CameraShake {
+    trauma: f32,
+    rng: Rng,
+}
+
+impl Default for CameraShake {
+    fn default() -> Self {
+        CameraShake {
+            trauma: 0.0,
+            rng: Rng::new(0),
+        }
+    }
+}
+
+impl CameraShake {
+    pub fn add_trauma(&mut self, amount: f32) {
+        self.trauma = f32::min(1.0, self.trauma + amount);
+    }
+}
+
+#[derive(Default)]
+pub struct HitStop {
+    frames: u32,
+    normal_dt: Option<f32>,
+}
+
+fn squashed_scale() -> Vec3 {
+    Vec3::new(1.25, 0.6, 0.8)
+}
+
+pub struct Squash {
+    seconds: f32,
+}
+
+// A broken block left behind to flash before it goes, see level::break_block
+pub struct Flash {
+    seconds: f32,
+    lit: bool,
+}
+
+impl Default for Flash {
+    fn default() -> Self {
+        Flash {
+            seconds: FLASH_SECONDS,
+            lit: false,
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct BlockFlash {
+    material: Option<Handle<StandardMaterial>>,
+}
+
+pub fn setup_juice(mut block_flash: ResMut<BlockFlash>, mut materials: ResMut<Assets<StandardMaterial>>) {
+    block_flash.material = Some(materials.add(Color::rgb(4.0, 4.0, 4.0).into()));
+}
+
+pub fn juice_event_system(
+    mut commands: Commands,
+    settings: Res<Settings>,
+    mut shake: ResMut<CameraShake>,
+    mut hit_stop: ResMut<HitStop>,
+    mut contact_reader: Local<EventReader<Contacts>>,
+    contact_events: Res<Events<Contacts>>,
+    mut destroyed_reader: Local<EventReader<BlockDestroyed>>,
+    destroyed_events: Res<Events<BlockDestroyed>>,
+    mut lost_reader: Local<EventReader<BallLost>>,
+    lost_events: Res<Events<BallLost>>,
+) {
+    for contact in contact_reader.iter(&contact_events) {
+        if let Contacts::BallPaddle(_ball, paddle) = contact {
+            if settings.paddle_squash {
+                commands.insert(
+                    *paddle,
+                    (
+                        Squash { seconds: SQUASH_SECONDS },
+                        NonUniformScale(squashed_scale()),
+                    ),
+                );
+            }
+        }
+    }
+
+    let mut destroyed = 0;
+    for _ in destroyed_reader.iter(&destroyed_events) {
+        destroyed += 1;
+
+        if settings.screen_shake {
+            shake.add_trauma(BLOCK_TRAUMA);
+        }
+    }
+
+    if settings.hit_stop && destroyed >= BIG_HIT_BLOCKS {
+        hit_stop.frames = HIT_STOP_FRAMES;
+    }
+
+    for _ in lost_reader.iter(&lost_events) {
+        if settings.screen_shake {
+            shake.add_trauma(BALL_LOST_TRAUMA);
+        }
+    }
+}
+
+pub fn camera_shake_system(
+    time: Res<Time>,
+    settings: Res<Settings>,
+    mut shake: ResMut<CameraShake>,
+    mut query: Query<(&GameCamera, Mut<Transform>)>,
+) {
+    if !settings.screen_shake {
+        shake.trauma = 0.0;
+    }
+
+    // Squaring the trauma keeps small knocks subtle while big ones still land
+    let strength = MAX_SHAKE_OFFSET * shake.trauma * shake.trauma;
+    let offset = Vec3::new(
+        shake.rng.range(-1.0, 1.0) * strength,
+        shake.rng.range(-1.0, 1.0) * strength,
+        0.0,
+    );
+    shake.trauma = f32::max(0.0, shake.trauma - SHAKE_DECAY * time.delta_seconds);
+
+    for (camera, mut transform) in &mut query.iter() {
+        transform.value = Mat4::face_toward(
+            camera.eye + offset,
+            camera.target + offset,
+            Vec3::new(0.0, 1.0, 0.0),
+        );
+    }
+}
+
+// Freezes the physics step for a few frames after a big hit, and for as long
+// as the game is paused or the level is being edited
+pub fn hit_stop_system(
+    current_state: Res<CurrentState>,
+    mut hit_stop: ResMut<HitStop>,
+    mut integration_parameters: ResMut<IntegrationParameters>,
+) {
+    let paused = current_state.state == GameState::Paused || current_state.state == GameState::Editing;
+    if hit_stop.frames > 0 || paused {
+        if hit_stop.normal_dt.is_none() {
+            hit_stop.normal_dt = Some(integration_parameters.dt());
+        }
+        integration_parameters.set_dt(0.0);
+        if !paused {
+            hit_stop.frames -= 1;
+        }
+    } else if let Some(dt) = hit_stop.normal_dt.take() {
+        integration_parameters.set_dt(dt);
+    }
+}
+
+pub fn squash_system(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut query: Query<(Entity, Mut<Squash>, Mut<NonUniformScale>)>,
+) {
+    for (entity, mut squash, mut scale) in &mut query.iter() {
+        squash.seconds -= time.delta_seconds;
+        if squash.seconds <= 0.0 {
+            scale.0 = Vec3::one();
+            commands.remove_one::<Squash>(entity);
+        } else {
+            // Ease back towards the paddle's resting shape
+            let t = squash.seconds / SQUASH_SECONDS;
+            scale.0 = Vec3::one() + (squashed_scale() - Vec3::one()) * t;
+        }
+    }
+}
+
+// Lights broken blocks up for a couple of frames before they're despawned,
+// or despawns them straight away with the flash turned off
+pub fn flash_system(
+    mut commands: Commands,
+    time: Res<Time>,
+    settings: Res<Settings>,
+    block_flash: Res<BlockFlash>,
+    mut query: Query<(Entity, Mut<Flash>)>,
+) {
+    for (entity, mut flash) in &mut query.iter() {
+        if !flash.lit {
+            flash.lit = true;
+            match block_flash.material {
+                Some(material) if settings.block_flash => commands.insert_one(entity, material),
+                _ => {
+                    commands.despawn(entity);
+                    continue;
+                },
+            };
+        }
+
+        flash.seconds -= time.delta_seconds;
+        if flash.seconds <= 0.0 {
+            commands.despawn(entity);
+        }
+    }
+}
@@ -0,0 +1,264 @@
+use bevy::prelude::*;
+use bevy_rapier3d::physics::RigidBodyHandleComponent;
+use bevy_rapier3d::rapier::dynamics::{JointSet, RigidBodySet};
+use bevy_rapier3d::rapier::geometry::{BroadPhase, ColliderSet, NarrowPhase};
+use bevy_rapier3d::rapier::pipeline::PhysicsPipeline;
+use crate::{Block, BlockDestroyed, CurrentState, GameState, Model, Paddle, Scoreboard, MAX_PLAYERS};
+use crate::attract::Attract;
+use crate::boss::BossPart;
+use crate::drones::{Drone, DroneDestroyed};
+use crate::level;
+use crate::modes::GameMode;
+use crate::powerups::ActivePowerUps;
+use crate::rng::Rng;
+
+// Versus keeps a laser for each player; everywhere else it's the one paddle's,
+// or shared by both in co-op
+const LASER_POWER_UPS: [&str; MAX_PLAYERS] = ["LASER", "LASER P2"];
+const LASER_SECONDS: f32 = 10.0;
+// Chance that a broken block powers up the paddle
+const LASER_CHANCE: f32 = 0.05;
+
+// Each shot takes a pair of bolts from the pool; with none free the paddle
+// can't fire until some land
+const POOL_SIZE: usize = 16;
+const FIRE_SECONDS: f32 = 0.25;
+const BOLT_SPEED: f32 = 60.0;
+// Bolts leave from near the ends of the paddle
+const BOLT_OFFSET_X: f32 = 3.0;
+const BOLT_HALF_WIDTH: f32 = 0.2;
+const BOLT_HALF_LENGTH: f32 = 0.8;
+const BOLT_Y: f32 = 3.0;
+// Spare bolts wait out of sight under the board
+const PARKED_Y: f32 = -100.0;
+// Past the top wall, or in versus the other paddle
+const BOLT_EXIT_Z: f32 = 40.0;
+
+const BLOCK_HALF_WIDTH: f32 = 4.0;
+const BLOCK_HALF_DEPTH: f32 = 1.0;
+
+pub struct LaserBolt {
+    active: bool,
+    // 1 up the board and -1 down it
+    direction: f32,
+    position: Vec3,
+}
+
+pub struct Laser {
+    // Seconds until each player's paddle can fire again
+    cooldowns: [f32; MAX_PLAYERS],
+    rng: Rng,
+}
+
+impl Default for Laser {
+    fn default() -> Self {
+        Laser {
+            cooldowns: [0.0; MAX_PLAYERS],
+            rng: Rng::new(0),
+        }
+    }
+}
+
+fn power_up(mode: GameMode, player: usize) -> &'static str {
+    if mode == GameMode::Versus {
+        LASER_POWER_UPS[player]
+    } else {
+        LASER_POWER_UPS[0]
+    }
+}
+
+pub fn setup_laser(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    let material = materials.add(Color::rgb(3.0, 0.3, 0.3).into());
+    for _ in 0..POOL_SIZE {
+        // The block mesh, thinned down to a streak
+        commands
+            .spawn(PbrComponents {
+                mesh: asset_server
+                    .load("assets/blender/block/export/block.gltf")
+                    .unwrap(),
+                material,
+                translation: Translation(Vec3::new(0.0, PARKED_Y, 0.0)),
+                ..Default::default()
+            })
+            .with(NonUniformScale(Vec3::new(BOLT_HALF_WIDTH / BLOCK_HALF_WIDTH, 0.3, BOLT_HALF_LENGTH)))
+            .with(LaserBolt {
+                active: false,
+                direction: 1.0,
+                position: Vec3::new(0.0, PARKED_Y, 0.0),
+            });
+    }
+}
+
+// Now and then a broken block leaves the laser behind, for whoever last
+// touched the ball
+pub fn laser_pickup_system(
+    mode: Res<GameMode>,
+    scoreboard: Res<Scoreboard>,
+    mut laser: ResMut<Laser>,
+    mut power_ups: ResMut<ActivePowerUps>,
+    mut destroyed_reader: Local<EventReader<BlockDestroyed>>,
+    destroyed_events: Res<Events<BlockDestroyed>>,
+) {
+    for _ in destroyed_reader.iter(&destroyed_events) {
+        if laser.rng.chance(LASER_CHANCE) {
+            power_ups.activate(power_up(*mode, scoreboard.last_touch), LASER_SECONDS);
+        }
+    }
+}
+
+// Up for the first player and W for the second fire twin bolts from the
+// paddle while the laser is on, as fast as the fire rate allows
+pub fn laser_fire_system(
+    time: Res<Time>,
+    keyboard_input: Res<Input<KeyCode>>,
+    mode: Res<GameMode>,
+    attract: Res<Attract>,
+    current_state: Res<CurrentState>,
+    power_ups: Res<ActivePowerUps>,
+    bodies: Res<RigidBodySet>,
+    mut laser: ResMut<Laser>,
+    mut bolts: Query<Mut<LaserBolt>>,
+    mut paddles: Query<(&Paddle, &RigidBodyHandleComponent)>,
+) {
+    for cooldown in laser.cooldowns.iter_mut() {
+        *cooldown = f32::max(0.0, *cooldown - time.delta_seconds);
+    }
+    if current_state.state != GameState::Playing || attract.active {
+        return;
+    }
+
+    let keys = [KeyCode::Up, KeyCode::W];
+    for (paddle, handle) in &mut paddles.iter() {
+        if !power_ups.is_active(power_up(*mode, paddle.player)) {
+            continue;
+        }
+        if !keyboard_input.pressed(keys[paddle.player]) || laser.cooldowns[paddle.player] > 0.0 {
+            continue;
+        }
+        let translation = match bodies.get(handle.handle()) {
+            Some(body) => body.position.translation,
+            None => continue,
+        };
+
+        let mut pool = bolts.iter();
+        let mut free: Vec<Mut<LaserBolt>> = pool.iter().filter(|bolt| !bolt.active).take(2).collect();
+        if free.len() < 2 {
+            continue;
+        }
+        laser.cooldowns[paddle.player] = FIRE_SECONDS;
+
+        // Bolts head away from the paddle's own end of the board
+        let direction = if paddle.home_z < 0.0 { 1.0 } else { -1.0 };
+        for (bolt, side) in free.iter_mut().zip([-1.0, 1.0].iter()) {
+            bolt.active = true;
+            bolt.direction = direction;
+            bolt.position = Vec3::new(
+                translation.x + side * BOLT_OFFSET_X,
+                BOLT_Y,
+                translation.z + direction * (1.0 + BOLT_HALF_LENGTH),
+            );
+        }
+    }
+}
+
+// Flies the bolts and breaks what they hit. A bolt takes one hit off a block
+// like the ball does and goes through the same level::break_block for
+// scoring, sound and particles. Bolts knock drones out, though only the ball
+// scores for them, and glance off the boss without hurting it.
+pub fn laser_bolt_system(
+    mut commands: Commands,
+    time: Res<Time>,
+    current_state: Res<CurrentState>,
+    mut pipeline: ResMut<PhysicsPipeline>,
+    mut broad_phase: ResMut<BroadPhase>,
+    mut narrow_phase: ResMut<NarrowPhase>,
+    mut bodies: ResMut<RigidBodySet>,
+    mut colliders: ResMut<ColliderSet>,
+    mut joints: ResMut<JointSet>,
+    mut destroyed_events: ResMut<Events<BlockDestroyed>>,
+    mut drone_events: ResMut<Events<DroneDestroyed>>,
+    mut bolts: Query<(Mut<LaserBolt>, Mut<Translation>)>,
+    mut blocks: Query<(Entity, Mut<Block>, &Model, &RigidBodyHandleComponent)>,
+    mut drones: Query<Mut<Drone>>,
+    mut boss_parts: Query<(&BossPart, &Translation)>,
+) {
+    let playing = current_state.state == GameState::Playing;
+    let bolt_size = Vec2::new(BOLT_HALF_WIDTH, BOLT_HALF_LENGTH);
+
+    for (mut bolt, mut translation) in &mut bolts.iter() {
+        if !bolt.active {
+            continue;
+        }
+
+        bolt.position += Vec3::new(0.0, 0.0, bolt.direction * BOLT_SPEED * time.delta_seconds);
+        let position = bolt.position;
+        let mut landed = !playing || position.z().abs() > BOLT_EXIT_Z;
+
+        if !landed {
+            for (part, centre) in &mut boss_parts.iter() {
+                if part.blocks(centre, position, bolt_size) {
+                    landed = true;
+                    break;
+                }
+            }
+        }
+
+        if !landed {
+            for mut drone in &mut drones.iter() {
+                if drone.touches(position, BOLT_HALF_WIDTH) {
+                    drone_events.send(drone.knock_out());
+                    landed = true;
+                    break;
+                }
+            }
+        }
+
+        if !landed {
+            for (entity, mut block, model, handle) in &mut blocks.iter() {
+                // Blocks already broken this frame have left the physics world
+                let block_translation = match bodies.get(handle.handle()) {
+                    Some(body) => body.position.translation,
+                    None => continue,
+                };
+                if (block_translation.x - position.x()).abs() >= BLOCK_HALF_WIDTH + BOLT_HALF_WIDTH
+                    || (block_translation.z - position.z()).abs() >= BLOCK_HALF_DEPTH + BOLT_HALF_LENGTH
+                {
+                    continue;
+                }
+                landed = true;
+
+                if !block.kind.destructible() {
+                    break;
+                }
+                block.hits_left = block.hits_left.saturating_sub(1);
+                if block.hits_left == 0 {
+                    level::break_block(
+                        &mut commands,
+                        &mut pipeline,
+                        &mut broad_phase,
+                        &mut narrow_phase,
+                        &mut bodies,
+                        &mut colliders,
+                        &mut joints,
+                        &mut destroyed_events,
+                        entity,
+                        handle.handle(),
+                        block.kind,
+                        model.color,
+                    );
+                }
+                break;
+            }
+        }
+
+        if landed {
+            bolt.active = false;
+            bolt.position = Vec3::new(0.0, PARKED_Y, 0.0);
+        }
+        translation.0 = bolt.position;
+    }
+}
@@ -0,0 +1,605 @@
+use std::fs;
+use std::path::Path;
+use bevy::prelude::*;
+use bevy_rapier3d::physics::{ColliderHandleComponent, RigidBodyHandleComponent};
+use bevy_rapier3d::rapier::dynamics::{RigidBodyBuilder, RigidBodyHandle, RigidBodySet, JointSet};
+use bevy_rapier3d::rapier::geometry::{ColliderBuilder, BroadPhase, NarrowPhase, ColliderSet};
+use bevy_rapier3d::rapier::pipeline::PhysicsPipeline;
+use serde::{Deserialize, Serialize};
+use crate::{Block, BlockDestroyed, Model};
+use crate::boss::{BossAttack, BossSpec};
+use crate::drones::{DroneBehavior, DroneSpawner};
+use crate::generator;
+use crate::juice::Flash;
+use crate::modes::GameMode;
+use crate::motion::{BlockMotion, MovingBlock};
+
+// Levels saved from the editor, loaded after the built-in ones
+pub const LEVELS_DIRECTORY: &str = "assets/levels";
+
+pub const DEFAULT_BALL_SPEED: f32 = 30.0;
+
+// Physics blocks knocked off the end of the board are gone once they drop
+// this far
+const FALLEN_Y: f32 = -10.0;
+
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BlockKind {
+    Standard,
+    // Takes two hits to break
+    Tough,
+    // Never breaks and doesn't count towards clearing the level
+    Unbreakable,
+    // Sits loose on the board, so the first hit knocks it about and the
+    // second breaks it
+    Physics,
+}
+
+impl Default for BlockKind {
+    fn default() -> Self {
+        BlockKind::Standard
+    }
+}
+
+impl BlockKind {
+    pub fn all() -> Vec<BlockKind> {
+        vec![BlockKind::Standard, BlockKind::Tough, BlockKind::Unbreakable, BlockKind::Physics]
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            BlockKind::Standard => "Standard",
+            BlockKind::Tough => "Tough",
+            BlockKind::Unbreakable => "Unbreakable",
+            BlockKind::Physics => "Physics",
+        }
+    }
+
+    pub fn hits(self) -> usize {
+        match self {
+            BlockKind::Tough | BlockKind::Physics => 2,
+            _ => 1,
+        }
+    }
+
+    pub fn destructible(self) -> bool {
+        self != BlockKind::Unbreakable
+    }
+}
+
+// Colors are stored as [r, g, b] in level files
+mod color_rgb {
+    use bevy::prelude::Color;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(color: &Color, serializer: S) -> Result<S::Ok, S::Error> {
+        [color.r, color.g, color.b].serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Color, D::Error> {
+        let [r, g, b] = <[f32; 3]>::deserialize(deserializer)?;
+        Ok(Color::rgb(r, g, b))
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct BlockSpec {
+    pub x: f32,
+    pub z: f32,
+    #[serde(with = "color_rgb")]
+    pub color: Color,
+    #[serde(default)]
+    pub kind: BlockKind,
+    // Blocks without one stay put
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub motion: Option<BlockMotion>,
+}
+
+fn default_ball_speed() -> f32 {
+    DEFAULT_BALL_SPEED
+}
+
+// The level file format, one JSON file per level
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Level {
+    pub name: String,
+    // Track name under assets/music, see music.rs
+    pub music: Option<String>,
+    #[serde(default = "default_ball_speed")]
+    pub ball_speed: f32,
+    pub blocks: Vec<BlockSpec>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub drones: Vec<DroneSpawner>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub boss: Option<BossSpec>,
+    // Where the level was loaded from, so the editor saves back to it
+    #[serde(skip)]
+    pub path: Option<String>,
+    // Edited in the editor since it was last loaded or saved
+    #[serde(skip)]
+    pub unsaved: bool,
+}
+
+impl Level {
+    pub fn load(path: &Path) -> Result<Level, String> {
+        let contents = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        let mut level: Level = serde_json::from_str(&contents).map_err(|e| e.to_string())?;
+        level.path = Some(path.to_string_lossy().to_string());
+        Ok(level)
+    }
+
+    pub fn file_name(&self) -> String {
+        let stem: String = self
+            .name
+            .to_lowercase()
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect();
+        format!("{}.json", stem)
+    }
+
+    // Levels without a file yet are saved under their name
+    pub fn save(&mut self) -> Result<String, String> {
+        let path = match &self.path {
+            Some(path) => path.clone(),
+            None => format!("{}/{}", LEVELS_DIRECTORY, self.file_name()),
+        };
+
+        self.save_to(Path::new(&path))?;
+        self.path = Some(path.clone());
+        self.unsaved = false;
+        Ok(path)
+    }
+
+    pub fn save_to(&self, path: &Path) -> Result<(), String> {
+        if let Some(directory) = path.parent() {
+            fs::create_dir_all(directory).map_err(|e| e.to_string())?;
+        }
+        let contents = serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
+        fs::write(path, contents).map_err(|e| e.to_string())
+    }
+}
+
+pub struct Levels {
+    pub levels: Vec<Level>,
+    pub current: usize,
+    // Shared field in the middle of the board for two player versus
+    pub versus: Level,
+    // Replaced by a newly generated level at every endless stage
+    pub endless: Level,
+    // The day's challenge, see daily.rs
+    pub daily: Level,
+    // Starting rows for survival, which brings in the rest as it goes
+    pub survival: Level,
+}
+
+// Sent whenever the block field should be rebuilt from the current level
+pub struct ResetLevel;
+
+impl Levels {
+    pub fn builtin() -> Self {
+        Levels {
+            levels: vec![classic(), pyramid(), checkerboard(), carousel(), warden()],
+            current: 0,
+            versus: versus(),
+            endless: generator::generate(0, 0),
+            daily: generator::generate(0, 0),
+            survival: generator::generate(0, 0),
+        }
+    }
+
+    // The built-in levels followed by any level files, in file name order. A
+    // file with a built-in level's name replaces it, so built-in levels can be
+    // edited and saved like any other.
+    pub fn load() -> Self {
+        let mut levels = Levels::builtin();
+
+        let mut paths: Vec<_> = fs::read_dir(LEVELS_DIRECTORY)
+            .map(|entries| entries.filter_map(|entry| entry.ok()).map(|entry| entry.path()).collect())
+            .unwrap_or_default();
+        paths.sort();
+        for path in paths {
+            if path.extension().map_or(false, |extension| extension == "json") {
+                match Level::load(&path) {
+                    Ok(level) => match levels.levels.iter().position(|existing| existing.name == level.name) {
+                        Some(index) => levels.levels[index] = level,
+                        None => levels.levels.push(level),
+                    },
+                    Err(e) => eprintln!("Ignoring level {}: {}", path.display(), e),
+                }
+            }
+        }
+
+        levels
+    }
+
+    pub fn current(&self) -> &Level {
+        &self.levels[self.current]
+    }
+
+    pub fn for_mode(&self, mode: GameMode) -> &Level {
+        match mode {
+            GameMode::Versus => &self.versus,
+            GameMode::Endless => &self.endless,
+            GameMode::Daily => &self.daily,
+            GameMode::Survival => &self.survival,
+            _ => self.current(),
+        }
+    }
+
+    pub fn advance(&mut self) {
+        self.current = (self.current + 1) % self.levels.len();
+    }
+}
+
+fn classic() -> Level {
+    let mut blocks = vec![];
+    for z_pos in 5..35 {
+        if z_pos % 5 == 0 {
+            for x_pos in -25..30 {
+                if x_pos % 10 == 0 {
+                    blocks.push(BlockSpec {
+                        x: x_pos as f32,
+                        z: z_pos as f32,
+                        color: Color::rgb(2.3, 2.3, 0.0),
+                        kind: BlockKind::Standard,
+                        motion: None,
+                    });
+                }
+            }
+        }
+    }
+
+    Level {
+        name: "Classic".to_string(),
+        music: Some("classic".to_string()),
+        ball_speed: DEFAULT_BALL_SPEED,
+        blocks,
+        drones: vec![],
+        boss: None,
+        path: None,
+        unsaved: false,
+    }
+}
+
+fn pyramid() -> Level {
+    let mut blocks = vec![];
+    for row in 0..5 {
+        let z_pos = 10 + row * 5;
+        for column in -(4 - row)..(5 - row) {
+            blocks.push(BlockSpec {
+                x: column as f32 * 5.0,
+                z: z_pos as f32,
+                color: Color::rgb(2.3, 0.4 * row as f32, 0.0),
+                kind: BlockKind::Standard,
+                motion: None,
+            });
+        }
+    }
+
+    Level {
+        name: "Pyramid".to_string(),
+        music: Some("pyramid".to_string()),
+        ball_speed: DEFAULT_BALL_SPEED,
+        blocks,
+        drones: vec![],
+        boss: None,
+        path: None,
+        unsaved: false,
+    }
+}
+
+fn checkerboard() -> Level {
+    let mut blocks = vec![];
+    for row in 0..6 {
+        for column in 0..6 {
+            if (row + column) % 2 == 0 {
+                blocks.push(BlockSpec {
+                    x: -25.0 + column as f32 * 10.0,
+                    z: 5.0 + row as f32 * 5.0,
+                    color: Color::rgb(0.0, 2.3, 0.4),
+                    kind: BlockKind::Standard,
+                    motion: None,
+                });
+            }
+        }
+    }
+
+    Level {
+        name: "Checkerboard".to_string(),
+        music: Some("checkerboard".to_string()),
+        ball_speed: DEFAULT_BALL_SPEED,
+        blocks,
+        drones: vec![],
+        boss: None,
+        path: None,
+        unsaved: false,
+    }
+}
+
+// Sliders, orbiters and a patrolling tough block under a fixed top row, with
+// the odd drone drifting down
+fn carousel() -> Level {
+    let mut blocks = vec![];
+    for column in 0..6 {
+        blocks.push(BlockSpec {
+            x: -25.0 + column as f32 * 10.0,
+            z: 35.0,
+            color: Color::rgb(0.4, 0.4, 2.3),
+            kind: BlockKind::Standard,
+            motion: None,
+        });
+    }
+    for column in -1..2 {
+        blocks.push(BlockSpec {
+            x: column as f32 * 20.0,
+            z: 30.0,
+            color: Color::rgb(0.0, 2.3, 2.3),
+            kind: BlockKind::Standard,
+            motion: Some(BlockMotion::Slide {
+                distance: 5.0,
+                // The middle one goes the other way to the outer two
+                speed: if column == 0 { -8.0 } else { 8.0 },
+            }),
+        });
+    }
+    for x in [-20.0, 10.0].iter() {
+        blocks.push(BlockSpec {
+            x: *x,
+            z: 20.0,
+            color: Color::rgb(2.3, 0.0, 2.3),
+            kind: BlockKind::Standard,
+            motion: Some(BlockMotion::Orbit {
+                radius: 5.0,
+                speed: 6.0,
+            }),
+        });
+    }
+    blocks.push(BlockSpec {
+        x: -25.0,
+        z: 10.0,
+        color: Color::rgb(2.3, 1.2, 0.0),
+        kind: BlockKind::Tough,
+        motion: Some(BlockMotion::Patrol {
+            waypoints: vec![[50.0, 0.0]],
+            speed: 10.0,
+        }),
+    });
+
+    Level {
+        name: "Carousel".to_string(),
+        music: Some("checkerboard".to_string()),
+        ball_speed: DEFAULT_BALL_SPEED,
+        blocks,
+        drones: vec![DroneSpawner {
+            x: 0.0,
+            z: 36.0,
+            behavior: DroneBehavior::Drift,
+            interval: 12.0,
+            max_alive: 1,
+        }],
+        boss: None,
+        path: None,
+        unsaved: false,
+    }
+}
+
+// A boss fight, with a row of unbreakable blocks for cover and a few
+// standard ones to start
+fn warden() -> Level {
+    let mut blocks = vec![];
+    for x in [-20.0, 20.0].iter() {
+        blocks.push(BlockSpec {
+            x: *x,
+            z: 0.0,
+            color: Color::rgb(0.5, 0.5, 0.5),
+            kind: BlockKind::Unbreakable,
+            motion: None,
+        });
+    }
+    for column in 0..6 {
+        blocks.push(BlockSpec {
+            x: -25.0 + column as f32 * 10.0,
+            z: 10.0,
+            color: Color::rgb(1.2, 0.2, 1.6),
+            kind: BlockKind::Standard,
+            motion: None,
+        });
+    }
+
+    Level {
+        name: "Warden".to_string(),
+        music: Some("classic".to_string()),
+        ball_speed: DEFAULT_BALL_SPEED,
+        blocks,
+        drones: vec![],
+        boss: Some(BossSpec {
+            name: "Warden".to_string(),
+            health: 24,
+            z: 26.0,
+            sway: 6.0,
+            sway_speed: 4.0,
+            attack_seconds: 5.0,
+            attacks: vec![
+                BossAttack::Volley { count: 3, speed: 20.0 },
+                BossAttack::Blocks { count: 3 },
+            ],
+        }),
+        path: None,
+        unsaved: false,
+    }
+}
+
+// Mirrored around the centre line so neither end has an advantage
+fn versus() -> Level {
+    let mut blocks = vec![];
+    for row in -2..3 {
+        for column in 0..6 {
+            if row == 0 && (column == 2 || column == 3) {
+                continue;
+            }
+            blocks.push(BlockSpec {
+                x: -25.0 + column as f32 * 10.0,
+                z: row as f32 * 5.0,
+                color: Color::rgb(2.3, 0.4, 2.3),
+                kind: BlockKind::Standard,
+                motion: None,
+            });
+        }
+    }
+
+    Level {
+        name: "Versus".to_string(),
+        music: Some("versus".to_string()),
+        ball_speed: DEFAULT_BALL_SPEED,
+        blocks,
+        drones: vec![],
+        boss: None,
+        path: None,
+        unsaved: false,
+    }
+}
+
+pub fn block_bundle(spec: &BlockSpec) -> (Model, RigidBodyBuilder, ColliderBuilder, Block) {
+    // Blocks stay where the level puts them unless they move, which motion.rs
+    // drives, or are physics blocks left to the simulation
+    let body = match (&spec.motion, spec.kind) {
+        (Some(_), _) => RigidBodyBuilder::new_kinematic(),
+        (None, BlockKind::Physics) => RigidBodyBuilder::new_dynamic(),
+        (None, _) => RigidBodyBuilder::new_static(),
+    };
+
+    (
+        Model {
+            mesh: "assets/blender/block/export/block.gltf",
+            color: spec.color,
+        },
+        body.translation(spec.x, 3.0, spec.z),
+        ColliderBuilder::cuboid(4.0, 1.0, 1.0),
+        Block {
+            kind: spec.kind,
+            hits_left: spec.kind.hits(),
+        },
+    )
+}
+
+pub fn spawn_block(commands: &mut Commands, spec: &BlockSpec) -> Entity {
+    let block_entity = Entity::new();
+    commands.spawn_as_entity(block_entity, block_bundle(spec));
+    if let Some(motion) = &spec.motion {
+        commands.with(MovingBlock::new(motion.clone(), spec.x, spec.z));
+    }
+
+    block_entity
+}
+
+// Every way of breaking a block ends here: the ball, the laser and falling
+// off the board all score, sound and burst through the BlockDestroyed event.
+// The body leaves the physics world straight away, so anything counting
+// standing blocks later in the frame doesn't see it. The entity stays on as
+// a Flash for juice.rs to light up and despawn.
+pub fn break_block(
+    commands: &mut Commands,
+    pipeline: &mut PhysicsPipeline,
+    broad_phase: &mut BroadPhase,
+    narrow_phase: &mut NarrowPhase,
+    bodies: &mut RigidBodySet,
+    colliders: &mut ColliderSet,
+    joints: &mut JointSet,
+    destroyed_events: &mut Events<BlockDestroyed>,
+    entity: Entity,
+    handle: RigidBodyHandle,
+    kind: BlockKind,
+    color: Color,
+) {
+    let translation = match bodies.get(handle) {
+        Some(body) => body.position.translation,
+        None => return,
+    };
+    destroyed_events.send(BlockDestroyed {
+        position: Vec3::new(translation.x, translation.y, translation.z),
+        color,
+        kind,
+    });
+
+    pipeline.remove_rigid_body(handle, broad_phase, narrow_phase, bodies, colliders, joints);
+    commands.remove_one::<Block>(entity);
+    commands.remove_one::<RigidBodyHandleComponent>(entity);
+    commands.remove_one::<ColliderHandleComponent>(entity);
+    commands.insert_one(entity, Flash::default());
+}
+
+pub fn level_reset_system(
+    mut commands: Commands,
+    mut reader: Local<EventReader<ResetLevel>>,
+    reset_events: Res<Events<ResetLevel>>,
+    levels: Res<Levels>,
+    mode: Res<GameMode>,
+    mut pipeline: ResMut<PhysicsPipeline>,
+    mut broad_phase: ResMut<BroadPhase>,
+    mut narrow_phase: ResMut<NarrowPhase>,
+    mut bodies: ResMut<RigidBodySet>,
+    mut colliders: ResMut<ColliderSet>,
+    mut joints: ResMut<JointSet>,
+    mut blocks: Query<(Entity, &Block, &RigidBodyHandleComponent)>,
+) {
+    if reader.iter(&reset_events).last().is_none() {
+        return;
+    }
+
+    for (entity, _block, body_handle) in &mut blocks.iter() {
+        pipeline.remove_rigid_body(
+            body_handle.handle(),
+            &mut broad_phase,
+            &mut narrow_phase,
+            &mut bodies,
+            &mut colliders,
+            &mut joints,
+        );
+        commands.despawn(entity);
+    }
+
+    for spec in levels.for_mode(*mode).blocks.iter() {
+        spawn_block(&mut commands, spec);
+    }
+}
+
+// A physics block that falls off the board counts as broken, otherwise a level
+// could be left with a block nobody can reach
+pub fn fallen_block_system(
+    mut commands: Commands,
+    mut pipeline: ResMut<PhysicsPipeline>,
+    mut broad_phase: ResMut<BroadPhase>,
+    mut narrow_phase: ResMut<NarrowPhase>,
+    mut bodies: ResMut<RigidBodySet>,
+    mut colliders: ResMut<ColliderSet>,
+    mut joints: ResMut<JointSet>,
+    mut destroyed_events: ResMut<Events<BlockDestroyed>>,
+    mut blocks: Query<(Entity, &Block, &Model, &RigidBodyHandleComponent)>,
+) {
+    for (entity, block, model, handle) in &mut blocks.iter() {
+        if block.kind != BlockKind::Physics {
+            continue;
+        }
+        match bodies.get(handle.handle()) {
+            Some(body) if body.position.translation.y < FALLEN_Y => (),
+            _ => continue,
+        }
+
+        break_block(
+            &mut commands,
+            &mut pipeline,
+            &mut broad_phase,
+            &mut narrow_phase,
+            &mut bodies,
+            &mut colliders,
+            &mut joints,
+            &mut destroyed_events,
+            entity,
+            handle.handle(),
+            block.kind,
+            model.color,
+        );
+    }
+}
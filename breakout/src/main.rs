@@ -25,37 +25,273 @@ use bevy_rapier3d::rapier::geometry::{
 use bevy_rapier3d::rapier::dynamics::*;
 use bevy_rapier3d::rapier::pipeline::PhysicsPipeline;
 
+mod attract;
+mod audio;
+mod boss;
+mod camera;
+mod daily;
+mod drones;
+mod editor;
+mod env;
+mod generator;
+mod highscores;
+mod hud;
+mod juice;
+mod laser;
+mod level;
+mod modes;
+mod motion;
+mod music;
+mod netplay;
+mod particles;
+mod powerups;
+mod remote;
+mod rng;
+mod scoring;
+mod settings;
+mod spectate;
+mod survival;
+mod time_attack;
+mod window;
+
+use attract::Attract;
+use audio::{AudioBackend, Sounds};
+use camera::CameraRig;
+use daily::Daily;
+use boss::{BossEvent, BossFight};
+use drones::{DroneDestroyed, DroneSpawning};
+use editor::Editor;
+use generator::Endless;
+use highscores::HighScores;
+use juice::{BlockFlash, CameraShake, HitStop};
+use laser::Laser;
+use level::{BlockKind, Levels, ResetLevel};
+use modes::{GameMode, MatchResult};
+use music::MusicPlayer;
+use particles::Particles;
+use powerups::ActivePowerUps;
+use scoring::{Combo, LevelCleared, PointsAwarded, ScoringRules};
+use settings::Settings;
+use spectate::SpectatorHost;
+use survival::{Survival, SurvivalHighScores};
+use time_attack::TimeAttack;
+use window::ScaledText;
+
 fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    if let Some(position) = args.iter().position(|arg| arg == "--env-bench") {
+        let episodes = args
+            .get(position + 1)
+            .and_then(|arg| arg.parse().ok())
+            .unwrap_or(100);
+        env::run_benchmark(episodes);
+        return;
+    }
+    if let Some(position) = args.iter().position(|arg| arg == "--remote") {
+        let target = args.get(position + 1).cloned().unwrap_or("stdio".to_string());
+        remote::run_server(target);
+        return;
+    }
+    if let Some(position) = args.iter().position(|arg| arg == "--remote-client") {
+        let target = args.get(position + 1).cloned().unwrap_or("stdio".to_string());
+        let episodes = args
+            .get(position + 2)
+            .and_then(|arg| arg.parse().ok())
+            .unwrap_or(3);
+        remote::run_client(target, episodes);
+        return;
+    }
+    if let Some(position) = args.iter().position(|arg| arg == "--netplay") {
+        let player = args
+            .get(position + 1)
+            .and_then(|arg| arg.parse().ok())
+            .filter(|player| *player < MAX_PLAYERS)
+            .unwrap_or(0);
+        let local = args.get(position + 2).cloned().unwrap_or("0.0.0.0:7400".to_string());
+        let peer = args.get(position + 3).cloned().unwrap_or("127.0.0.1:7401".to_string());
+        let frames = args
+            .get(position + 4)
+            .and_then(|arg| arg.parse().ok())
+            .unwrap_or(3600);
+        let bot = args.iter().any(|arg| arg == "--netplay-bot");
+        netplay::run_peer(player, local, peer, frames, netplay::LinkConditions::from_args(&args), bot);
+        return;
+    }
+    if let Some(position) = args.iter().position(|arg| arg == "--netplay-loopback") {
+        let frames = args
+            .get(position + 1)
+            .and_then(|arg| arg.parse().ok())
+            .unwrap_or(3600);
+        netplay::run_loopback(frames, netplay::LinkConditions::from_args(&args));
+        return;
+    }
+    if let Some(position) = args.iter().position(|arg| arg == "--spectate") {
+        let address = args.get(position + 1).cloned().unwrap_or(spectate::DEFAULT_ADDRESS.to_string());
+        spectate::run_client(address);
+        return;
+    }
+    if let Some(position) = args.iter().position(|arg| arg == "--generate") {
+        let seed = args
+            .get(position + 1)
+            .and_then(|arg| arg.parse().ok())
+            .unwrap_or_else(generator::seed_from_clock);
+        let count = args
+            .get(position + 2)
+            .and_then(|arg| arg.parse().ok())
+            .unwrap_or(1);
+        let directory = args.get(position + 3).cloned().unwrap_or("generated".to_string());
+        generator::export(seed, count, &directory);
+        return;
+    }
+    let endless_seed = args
+        .iter()
+        .position(|arg| arg == "--seed")
+        .and_then(|position| args.get(position + 1))
+        .and_then(|arg| arg.parse().ok())
+        .unwrap_or_else(generator::seed_from_clock);
+    let spectator_host = match args.iter().position(|arg| arg == "--spectate-host") {
+        Some(position) => SpectatorHost::bind(args.get(position + 1).map_or(spectate::DEFAULT_ADDRESS, |arg| arg.as_str())),
+        None => SpectatorHost::default(),
+    };
+
+    let settings = Settings::load();
+    let window_mode = if settings.fullscreen {
+        WindowMode::BorderlessFullscreen
+    } else {
+        WindowMode::Windowed
+    };
+
     App::build()
         .add_resource(Msaa { samples: 4 })
         .add_resource(BodyHandleToEntity(HashMap::new()))
-        .add_resource(Scoreboard { score: 0 })
-        .add_resource(CurrentState { state: GameState::ArenaStart } )
+        .add_resource(Scoreboard::default())
+        .add_resource(Lives { count: [START_LIVES; MAX_PLAYERS] })
+        .add_resource(RunTimer { seconds: 0.0 })
+        .add_resource(ActivePowerUps::default())
+        .add_resource(CurrentState::new(GameState::ArenaStart))
+        .add_resource(PaddleInput { direction: [0.0; MAX_PLAYERS], serve: false })
+        .add_resource(Levels::load())
+        .add_resource(GameMode::Classic)
+        .add_resource(MatchResult::default())
+        .add_resource(Endless::new(endless_seed))
+        .add_resource(Daily::load())
+        .add_resource(TimeAttack::load())
+        .add_resource(Survival::new(endless_seed))
+        .add_resource(SurvivalHighScores(HighScores::load(survival::SURVIVAL_HIGH_SCORES_PATH)))
+        .add_resource(HighScores::load("highscores.txt"))
+        .add_resource(Attract::default())
+        .add_resource(Editor::default())
+        .add_resource(DroneSpawning::default())
+        .add_resource(BossFight::default())
+        .add_resource(Laser::default())
+        .add_event::<ResetLevel>()
+        .add_event::<BallLost>()
+        .add_event::<RunOver>()
+        .add_event::<Contacts>()
+        .add_event::<BlockDestroyed>()
+        .add_event::<DroneDestroyed>()
+        .add_event::<BossEvent>()
+        .add_event::<StateChanged>()
+        .add_event::<PointsAwarded>()
+        .add_event::<LevelCleared>()
+        .add_resource(ScoringRules::load())
+        .add_resource(Combo::default())
+        .add_resource(settings)
+        .add_resource(CameraShake::default())
+        .add_resource(HitStop::default())
+        .add_resource(BlockFlash::default())
+        .add_resource(Particles::default())
+        .add_resource(AudioBackend::detect(args.iter().any(|arg| arg == "--mute")))
+        .add_resource(Sounds::load())
+        .add_resource(MusicPlayer::default())
+        .add_resource(CameraRig::default())
+        .add_resource(spectator_host)
         .add_resource(WindowDescriptor {
             width: 1920,
             height: 1080,
             vsync: true,
-            resizable: false,
-            mode: WindowMode::Windowed,
+            resizable: true,
+            mode: window_mode,
             ..Default::default()
         })
         .add_plugin(RapierPhysicsPlugin)
         //.add_plugin(RapierRenderPlugin)
         //.add_startup_system(setup_debug_colliders.system())
+        .add_startup_system(setup_arena.system())
         .add_startup_system(setup.system())
         .add_startup_system(setup_blocks.system())
+        .add_startup_system(particles::setup_particles.system())
+        .add_startup_system(juice::setup_juice.system())
+        .add_startup_system(laser::setup_laser.system())
+        .add_startup_system(hud::setup_hud.system())
+        .add_system(model_visuals_system.system())
+        .add_system(paddle_input_system.system())
+        .add_system(pause_system.system())
+        .add_system(attract::attract_idle_system.system())
+        .add_system(attract::attract_cycle_system.system())
+        .add_system(attract::attract_paddle_system.system())
+        .add_system(modes::mode_select_system.system())
+        .add_system(modes::mode_arena_system.system())
+        .add_system(generator::endless_system.system())
+        .add_system(editor::editor_toggle_system.system())
+        .add_system(editor::editor_cursor_system.system())
+        .add_system(editor::editor_system.system())
+        .add_system(level::level_reset_system.system())
+        .add_system(motion::moving_block_system.system())
+        .add_system(level::fallen_block_system.system())
+        .add_system(boss::boss_spawn_system.system())
         .add_system(paddle_movement_system.system())
         .add_system(body_to_entity_system.system())
+        .add_system(boss::boss_victory_system.system())
         .add_system(ball_movement_system.system())
+        .add_system(drones::drone_system.system())
+        .add_system(drones::drone_spawner_system.system())
+        .add_system(boss::boss_movement_system.system())
+        .add_system(boss::boss_hit_system.system())
+        .add_system(boss::boss_attack_system.system())
+        .add_system(daily::daily_system.system())
+        .add_system(time_attack::time_attack_system.system())
+        .add_system(time_attack::ghost_paddle_system.system())
+        .add_system(survival::survival_system.system())
+        .add_system(survival::survival_advance_system.system())
+        .add_system(survival::danger_line_system.system())
         .add_system(game_restart_system.system())
+        .add_system(state_change_system.system())
         .add_system(contact_system.system())
-        .add_system(scoreboard_system.system())
+        .add_system(laser::laser_fire_system.system())
+        .add_system(laser::laser_bolt_system.system())
+        .add_system(laser::laser_pickup_system.system())
+        .add_system(scoring::scoring_system.system())
+        .add_system(scoring::spawn_score_popup_system.system())
+        .add_system(scoring::score_popup_system.system())
         .add_system(infoboard_system.system())
+        .add_system(run_timer_system.system())
+        .add_system(hud::hud_system.system())
+        .add_system(hud::hud_timer_system.system())
+        .add_system(powerups::power_up_timer_system.system())
+        .add_system(spectate::spectator_host_system.system())
+        .add_system(settings::settings_toggle_system.system())
+        .add_system(window::ui_scale_system.system())
+        .add_system(juice::juice_event_system.system())
+        .add_system(camera::camera_cycle_system.system())
+        .add_system(camera::camera_mode_system.system())
+        .add_system(juice::camera_shake_system.system())
+        .add_system(juice::hit_stop_system.system())
+        .add_system(juice::squash_system.system())
+        .add_system(juice::flash_system.system())
+        .add_system(particles::particle_event_system.system())
+        .add_system(particles::ball_trail_system.system())
+        .add_system(particles::particle_system.system())
+        .add_system(audio::audio_event_system.system())
+        .add_system(music::music_event_system.system())
+        .add_system(music::music_fade_system.system())
+        .add_system(music::music_mute_system.system())
         .add_resource(Gravity(Vector3::new(0.0, -3.7279, 0.0)))
         .add_default_plugins()
         .run();
 }
 
+#[derive(Clone, Copy)]
 enum Contacts {
     BallBlock(Entity, Entity),
     BallSideWall(Entity, Entity),
@@ -63,14 +299,59 @@ enum Contacts {
     BallPaddle(Entity, Entity),
 }
 
+#[derive(Clone, Copy, PartialEq)]
 enum GameState {
     ArenaStart,
     Playing,
+    Paused,
     GameOver,
+    Editing,
 }
 
 struct CurrentState {
     state: GameState,
+    transitions: Vec<StateChanged>,
+}
+
+impl CurrentState {
+    fn new(state: GameState) -> Self {
+        CurrentState {
+            state,
+            transitions: vec![],
+        }
+    }
+
+    // Records the transition so state_change_system can announce it
+    fn set(&mut self, state: GameState) {
+        if self.state != state {
+            self.transitions.push(StateChanged { from: self.state, to: state });
+        }
+        self.state = state;
+    }
+}
+
+#[derive(Clone, Copy)]
+struct StateChanged {
+    from: GameState,
+    to: GameState,
+}
+
+// Sent with the player whose goal the ball went past
+struct BallLost {
+    player: usize,
+}
+
+// Sent with the final score when a single player or co-op run ends
+#[derive(Clone, Copy)]
+struct RunOver {
+    score: usize,
+    seconds: f32,
+}
+
+struct BlockDestroyed {
+    position: Vec3,
+    color: Color,
+    kind: BlockKind,
 }
 
 struct BodyHandleToEntity(HashMap<RigidBodyHandle, Entity>);
@@ -88,6 +369,8 @@ struct TopWallEntity(pub Entity);
 struct PaddleEntity(pub Entity);
 
 struct Block {
+    kind: BlockKind,
+    hits_left: usize,
 }
 
 struct Ball {
@@ -100,46 +383,86 @@ struct Wall {
 struct TopWall {
 }
 
+const PADDLE_HALF_WIDTH: f32 = 4.0;
+const PADDLE_MAX_X: f32 = 25.5;
+
 struct Paddle {
     speed: f32,
+    player: usize,
+    // Where the paddle waits between serves
+    home_x: f32,
+    home_z: f32,
+    min_x: f32,
+    max_x: f32,
+}
+
+impl Paddle {
+    fn new(player: usize, home_z: f32) -> Self {
+        Paddle {
+            speed: 50.0,
+            player,
+            home_x: 0.0,
+            home_z,
+            min_x: -PADDLE_MAX_X,
+            max_x: PADDLE_MAX_X,
+        }
+    }
 }
 
+const MAX_PLAYERS: usize = 2;
+
+#[derive(Clone, Default)]
 struct Scoreboard {
     score: usize,
+    // Points per player, credited to whoever last touched the ball
+    players: [usize; MAX_PLAYERS],
+    last_touch: usize,
+}
+
+impl Scoreboard {
+    fn reset(&mut self) {
+        *self = Scoreboard::default();
+    }
+}
+
+const START_LIVES: usize = 3;
+
+// Single player modes only use the first count
+struct Lives {
+    count: [usize; MAX_PLAYERS],
+}
+
+struct RunTimer {
+    seconds: f32,
+}
+
+struct PaddleInput {
+    direction: [f32; MAX_PLAYERS],
+    serve: bool,
 }
 
 struct Infoboard {
 }
 
+struct Model {
+    mesh: &'static str,
+    color: Color,
+}
+
+struct GameCamera {
+    eye: Vec3,
+    target: Vec3,
+}
+
 fn setup_blocks(
     mut commands: Commands,
-    asset_server: Res<AssetServer>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
+    levels: Res<Levels>,
+    mode: Res<GameMode>,
     ) {
 
-    for z_pos in 5..35 {
-        if z_pos % 5 == 0 {
-            for x_pos in -25..30 {
-                if x_pos % 10 == 0 {
-                    let block_entity = Entity::new();
-                    commands.spawn_as_entity(
-                        block_entity,
-                        PbrComponents {
-                            mesh: asset_server
-                                .load("assets/blender/block/export/block.gltf")
-                                .unwrap(),
-                            material: materials.add(Color::rgb(2.3, 2.3, 0.0).into()),
-                            ..Default::default()
-                        },
-                    )
-                    .with(RigidBodyBuilder::new_dynamic().translation(x_pos as f32, 3.0, z_pos as f32))
-                    .with(ColliderBuilder::cuboid(4.0, 1.0, 1.0))
-                    .with(Block {});
-
-                    commands.insert_resource(BlockEntity(block_entity));
-                }
-            }
-        }
+    for spec in levels.for_mode(*mode).blocks.iter() {
+        let block_entity = level::spawn_block(&mut commands, spec);
+        commands.insert_resource(BlockEntity(block_entity));
     }
 }
 
@@ -152,132 +475,144 @@ fn setup_debug_colliders(
     commands.spawn((debug_body, debug_collide));
     // - END DEBUG
 }
-    
 
-fn setup(
+// Everything the simulation needs; meshes are attached separately by
+// model_visuals_system so the arena can also run headless.
+fn setup_arena(
     mut commands: Commands,
-    asset_server: Res<AssetServer>,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>, 
 ) {
     // - Ball -
     let ball_entity = Entity::new();
+    let (body, collider) = ball_physics();
     commands.spawn_as_entity(
         ball_entity,
-        PbrComponents {
-            mesh: asset_server
-                .load("assets/blender/ball/export/ball.gltf")
-                .unwrap(),
-            material: materials.add(Color::rgb(0.7, 0.0, 0.0).into()),
-            ..Default::default()
-        },
-    )
-    .with(RigidBodyBuilder::new_dynamic()
-        .translation(0.0, 2.5, -20.0)
-        )
-    .with(ColliderBuilder::ball(1.0))
-    .with(Ball {
-        velocity: Vec3::new(-1.0, 0.0, -1.0).normalize(),
-    });
+        (
+            Model {
+                mesh: "assets/blender/ball/export/ball.gltf",
+                color: Color::rgb(0.7, 0.0, 0.0),
+            },
+            body,
+            collider,
+            Ball {
+                velocity: Vec3::new(-1.0, 0.0, -1.0).normalize(),
+            },
+        ),
+    );
     commands.insert_resource(BallEntity(ball_entity));
 
     // - Paddle -
-    let player_entity = Entity::new();
-    commands.spawn_as_entity(
-        player_entity,
-        PbrComponents {
-            mesh: asset_server
-                .load("assets/blender/paddle/export/paddle.gltf")
-                .unwrap(),
-            material: materials.add(Color::rgb(0.9, 0.92, 1.0).into()),
-            ..Default::default()
-        },
-    )
-    .with(RigidBodyBuilder::new_kinematic()
-        .translation(0.0, 3.0, -35.0))
-    .with(ColliderBuilder::cuboid(4.0, 1.0, 1.0))
-
-    .with(Paddle {
-        speed: 50.0
-    });
+    let player_entity = spawn_paddle(&mut commands, Paddle::new(0, -35.0));
     commands.insert_resource(PlayerEntity(player_entity));
 
     // - Left Wall -
     let left_wall_entity = Entity::new();
     commands.spawn_as_entity(
         left_wall_entity,
-        PbrComponents {
-            mesh: asset_server
-                .load("assets/blender/wall/export/wall.gltf")
-                .unwrap(),
-            material: materials.add(Color::rgb(0.0, 0.0, 2.04).into()),
-            ..Default::default()
-        },
-    )
-    .with(RigidBodyBuilder::new_static().translation(31.5, 1.0, 0.0))
-    .with(ColliderBuilder::cuboid(2.0, 3.0, 40.0))
-    .with(Wall {});
+        (
+            Model {
+                mesh: "assets/blender/wall/export/wall.gltf",
+                color: Color::rgb(0.0, 0.0, 2.04),
+            },
+            RigidBodyBuilder::new_static().translation(31.5, 1.0, 0.0),
+            ColliderBuilder::cuboid(2.0, 3.0, 40.0),
+            Wall {},
+        ),
+    );
     commands.insert_resource(WallEntity(left_wall_entity));
 
     // - Right Wall -
     let right_wall_entity = Entity::new();
     commands.spawn_as_entity(
         right_wall_entity,
-        PbrComponents {
-            mesh: asset_server
-                .load("assets/blender/wall/export/wall.gltf")
-                .unwrap(),
-            material: materials.add(Color::rgb(0.0, 0.0, 2.04).into()),
-            ..Default::default()
-        },
-    )
-    .with(RigidBodyBuilder::new_static().translation(-31.5, 1.0, 0.0))
-    .with(ColliderBuilder::cuboid(2.0, 3.0, 40.0))
-    .with(Wall {});
+        (
+            Model {
+                mesh: "assets/blender/wall/export/wall.gltf",
+                color: Color::rgb(0.0, 0.0, 2.04),
+            },
+            RigidBodyBuilder::new_static().translation(-31.5, 1.0, 0.0),
+            ColliderBuilder::cuboid(2.0, 3.0, 40.0),
+            Wall {},
+        ),
+    );
     commands.insert_resource(WallEntity(right_wall_entity));
-    
+
     // - Top Wall -
-    let top_wall_entity = Entity::new();
-    commands.spawn_as_entity(
-        top_wall_entity,
-        PbrComponents {
-        mesh: asset_server
-            .load("assets/blender/top_wall/export/top_wall.gltf")
-            .unwrap(),
-        material: materials.add(Color::rgb(0.0, 0.0, 2.04).into()),
-        translation: Translation::new(0.0, 0.0, 0.0),
-        rotation: Rotation::from_rotation_y(1.57),
-        ..Default::default()
-    })
-    .with(RigidBodyBuilder::new_static()
-        .translation(0.0, 1.0, 39.0)
-        .rotation(Vector3::new(0.0, 1.57, 0.0)))
-    .with(ColliderBuilder::cuboid(1.0, 3.0, 30.0));
+    let top_wall_entity = spawn_top_wall(&mut commands);
     commands.insert_resource(TopWallEntity(top_wall_entity));
 
-    // - Score Text
-    commands.spawn(TextComponents {
-        text: Text {
-            font: asset_server.load("assets/fonts/FiraSans-Bold.ttf").unwrap(),
-            value: "".to_string(),
-            style: TextStyle {
-                color: Color::rgb(0.2, 0.2, 0.8),
-                font_size: 40.0,
-            },
+    // - Board -
+    commands.spawn((
+        Model {
+            mesh: "assets/blender/board/export/board.gltf",
+            color: Color::rgb(0.0, 0.0, 2.04),
         },
-        style: Style {
-            position_type: PositionType::Absolute,
-            position: Rect {
-                top: Val::Px(25.0),
-                left: Val::Px(25.0),
-                ..Default::default()
+        RigidBodyBuilder::new_static()
+            .translation(0.0, 0.0, 0.0),
+        ColliderBuilder::cuboid(30.0, 2.0, 40.0),
+    ));
+}
+
+// The ball's and paddles' bodies, also rebuilt by netplay when it rolls back
+fn ball_physics() -> (RigidBodyBuilder, ColliderBuilder) {
+    (
+        RigidBodyBuilder::new_dynamic()
+            .translation(0.0, 2.5, -20.0),
+        ColliderBuilder::ball(1.0),
+    )
+}
+
+fn paddle_physics(paddle: &Paddle) -> (RigidBodyBuilder, ColliderBuilder) {
+    (
+        RigidBodyBuilder::new_kinematic()
+            .translation(paddle.home_x, 3.0, paddle.home_z),
+        ColliderBuilder::cuboid(PADDLE_HALF_WIDTH, 1.0, 1.0),
+    )
+}
+
+fn spawn_paddle(commands: &mut Commands, paddle: Paddle) -> Entity {
+    let paddle_entity = Entity::new();
+    let (body, collider) = paddle_physics(&paddle);
+    commands.spawn_as_entity(
+        paddle_entity,
+        (
+            Model {
+                mesh: "assets/blender/paddle/export/paddle.gltf",
+                color: Color::rgb(0.9, 0.92, 1.0),
             },
-            ..Default::default()
-        },
-        ..Default::default()
-    })
-    .with(Scoreboard {score: 0});
+            body,
+            collider,
+            paddle,
+        ),
+    );
 
+    paddle_entity
+}
+
+fn spawn_top_wall(commands: &mut Commands) -> Entity {
+    let top_wall_entity = Entity::new();
+    commands.spawn_as_entity(
+        top_wall_entity,
+        (
+            Model {
+                mesh: "assets/blender/top_wall/export/top_wall.gltf",
+                color: Color::rgb(0.0, 0.0, 2.04),
+            },
+            RigidBodyBuilder::new_static()
+                .translation(0.0, 1.0, 39.0)
+                .rotation(Vector3::new(0.0, 1.57, 0.0)),
+            ColliderBuilder::cuboid(1.0, 3.0, 30.0),
+        ),
+    );
+
+    top_wall_entity
+}
+
+fn setup(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>, 
+) {
     // - Info Text
     commands.spawn(TextComponents {
         text: Text {
@@ -291,30 +626,19 @@ fn setup(
         style: Style {
             position_type: PositionType::Absolute,
             position: Rect {
-                top: Val::Px(75.0),
-                left: Val::Px(600.0),
+                top: Val::Percent(7.0),
+                left: Val::Percent(31.25),
                 ..Default::default()
             },
             ..Default::default()
         },
         ..Default::default()
     })
-    .with(Infoboard {});
+    .with(Infoboard {})
+    .with(ScaledText { font_size: 40.0 });
 
 
     commands
-        // - Board - 
-        .spawn(PbrComponents {
-            mesh: asset_server
-                .load("assets/blender/board/export/board.gltf")
-                .unwrap(),
-            material: materials.add(Color::rgb(0.0, 0.0, 2.04).into()),
-            ..Default::default()
-        })
-        .with(RigidBodyBuilder::new_static()
-            .translation(0.0, 0.0, 0.0))
-        .with(ColliderBuilder::cuboid(30.0, 2.0, 40.0))
-
         // - Space - 
         .spawn(PbrComponents {
             mesh: meshes.add(Mesh::from(shape::Plane { size: 1000.0 })),
@@ -338,25 +662,35 @@ fn setup(
                 Vec3::new(0.0, 60.0, -85.0),
                 Vec3::new(0.0, 0.0, -10.0),
                 Vec3::new(0.0, 1.0, 0.0),
-
-                //Front - Side
-                /*
-                Vec3::new(0.0, 3.0, -75.0),
-                Vec3::new(0.0, 0.0, -20.0),
-                Vec3::new(0.0, 1.0, 0.0),
-                */
-
-                // Right - Side
-                /*
-                Vec3::new(-50.0, 2.0, -30.0),
-                Vec3::new(0.0, 0.0, -30.0),
-                Vec3::new(0.0, 1.0, 0.0),
-                */
             )),
             ..Default::default()
+        })
+        .with(GameCamera {
+            eye: Vec3::new(0.0, 60.0, -85.0),
+            target: Vec3::new(0.0, 0.0, -10.0),
         });
 }
 
+fn model_visuals_system(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut added: Query<(Entity, Added<Model>)>,
+) {
+    for (entity, model) in &mut added.iter() {
+        commands.insert(
+            entity,
+            PbrComponents {
+                mesh: asset_server
+                    .load(model.mesh)
+                    .unwrap(),
+                material: materials.add(model.color.into()),
+                ..Default::default()
+            },
+        );
+    }
+}
+
 fn body_to_entity_system(
     mut h_to_e: ResMut<BodyHandleToEntity>,
     mut added: Query<(Entity, Added<RigidBodyHandleComponent>)>,
@@ -368,22 +702,24 @@ fn body_to_entity_system(
 
 fn contact_system(
     mut commands: Commands,
-    mut scoreboard: ResMut<Scoreboard>,
     mut pipeline: ResMut<PhysicsPipeline>,
     mut broad_phase: ResMut<BroadPhase>,
     mut narrow_phase: ResMut<NarrowPhase>,
     mut bodies: ResMut<RigidBodySet>,
     mut colliders: ResMut<ColliderSet>,
     mut joints: ResMut<JointSet>,
+    mut contact_events: ResMut<Events<Contacts>>,
+    mut destroyed_events: ResMut<Events<BlockDestroyed>>,
 
     events: Res<EventQueue>,
     h_to_e: Res<BodyHandleToEntity>,
 
     balls: Query<Mut<Ball>>,
-    blocks: Query<Mut<Block>>,
+    mut blocks: Query<Mut<Block>>,
     side_walls: Query<Mut<Wall>>,
     top_walls: Query<Mut<TopWall>>,
     paddles: Query<Mut<Paddle>>,
+    models: Query<&Model>,
     handles: Query<&RigidBodyHandleComponent>,
 ) {
     let mut contacts = vec![];
@@ -427,6 +763,7 @@ fn contact_system(
     }
 
     for contact in contacts.into_iter() {
+        contact_events.send(contact);
         match contact {
             Contacts::BallBlock(e1, e2) => {
                 let ball_handle = handles
@@ -434,28 +771,43 @@ fn contact_system(
                     .unwrap()
                     .handle();
 
-                    scoreboard.score += 1;
                 {
                     // Richochet the ball when it hits a block
                     let mut ball_body = bodies.get_mut(ball_handle).unwrap();
                     ball_body.linvel.z = -ball_body.linvel.z;
                 }
 
+                // Tough blocks soak up a hit first and unbreakable ones never go
+                let mut kind = BlockKind::Standard;
+                if let Ok(mut block) = blocks.get_mut::<Block>(e2) {
+                    if !block.kind.destructible() {
+                        continue;
+                    }
+                    block.hits_left = block.hits_left.saturating_sub(1);
+                    if block.hits_left > 0 {
+                        continue;
+                    }
+                    kind = block.kind;
+                }
+
                 let block_handle = handles
                     .get::<RigidBodyHandleComponent>(e2)
                     .unwrap()
                     .handle();
-                
-                pipeline.remove_rigid_body(
-                    block_handle,
+                level::break_block(
+                    &mut commands,
+                    &mut pipeline,
                     &mut broad_phase,
                     &mut narrow_phase,
                     &mut bodies,
                     &mut colliders,
                     &mut joints,
+                    &mut destroyed_events,
+                    e2,
+                    block_handle,
+                    kind,
+                    models.get::<Model>(e2).map(|model| model.color).unwrap_or(Color::WHITE),
                 );
-
-                commands.despawn(e2);
             },
             Contacts::BallPaddle(e1, e2) => {
                 let ball_handle = handles
@@ -488,12 +840,46 @@ fn contact_system(
     }
 }
 
+fn state_change_system(
+    mut current_state: ResMut<CurrentState>,
+    mut state_events: ResMut<Events<StateChanged>>,
+) {
+    for transition in current_state.transitions.drain(..) {
+        state_events.send(transition);
+    }
+}
+
 fn game_restart_system(
+    attract: Res<Attract>,
+    editor: Res<Editor>,
+    mode: Res<GameMode>,
     mut current_state: ResMut<CurrentState>,
+    mut scoreboard: ResMut<Scoreboard>,
+    mut lives: ResMut<Lives>,
+    mut run_timer: ResMut<RunTimer>,
+    mut high_scores: ResMut<HighScores>,
+    mut match_result: ResMut<MatchResult>,
+    mut run_over_events: ResMut<Events<RunOver>>,
 ) {
     match current_state.state {
         GameState::GameOver => {
-            current_state.state = GameState::ArenaStart;
+            if *mode == GameMode::Versus {
+                match_result.winner = (0..MAX_PLAYERS).find(|&player| lives.count[player] > 0);
+            } else if !attract.active && !editor.playtesting {
+                // Survival keeps a table of its own, see survival.rs, and daily
+                // runs go to the day's history in daily.rs
+                if *mode != GameMode::Survival && *mode != GameMode::Daily {
+                    high_scores.record(scoreboard.score);
+                }
+                run_over_events.send(RunOver {
+                    score: scoreboard.score,
+                    seconds: run_timer.seconds,
+                });
+            }
+            scoreboard.reset();
+            lives.count = [START_LIVES; MAX_PLAYERS];
+            run_timer.seconds = 0.0;
+            current_state.set(GameState::ArenaStart);
         },
         _ => (),
     };
@@ -501,55 +887,81 @@ fn game_restart_system(
 
 fn ball_movement_system(
     time: Res<Time>,
-    keyboard_input: Res<Input<KeyCode>>,
+    paddle_input: Res<PaddleInput>,
+    mode: Res<GameMode>,
+    levels: Res<Levels>,
     events: Res<EventQueue>,
     ball_entity: Res<BallEntity>,
     mut current_state: ResMut<CurrentState>,
+    mut lives: ResMut<Lives>,
+    mut ball_lost_events: ResMut<Events<BallLost>>,
     mut bodies: ResMut<RigidBodySet>,
-    mut query: Query<(&RigidBodyHandleComponent, &Ball)>,
+    mut query: Query<(&RigidBodyHandleComponent, Mut<Ball>)>,
     mut infoboard_query: Query<(&mut Text, &Infoboard)>,
 ) {
     let delta_seconds = f32::min(0.2, time.delta_seconds);
 
     if let Ok(body_handle) = query.get::<RigidBodyHandleComponent>(ball_entity.0) {
         let mut body = bodies.get_mut(body_handle.handle()).unwrap();
-        let ball = query.get::<Ball>(ball_entity.0).unwrap();
+        let mut ball = query.get_mut::<Ball>(ball_entity.0).unwrap();
 
         match current_state.state {
             GameState::ArenaStart => {
-                if keyboard_input.pressed(KeyCode::Space) {
+                if paddle_input.serve {
                     body.position.translation.x = 0.0;
                     body.position.translation.y = 2.5;
-                    body.position.translation.z = -20.0;
-                    let x_impulse = -10.0; 
-                    let z_impulse = -10.0; 
+                    body.position.translation.z = mode.serve_z();
+                    let x_impulse = 10.0 * ball.velocity.x().signum();
+                    let z_impulse = 10.0 * ball.velocity.z().signum();
                     let impulse = Vector3::new(x_impulse, -10.0, z_impulse);
                     body.apply_impulse(impulse);
 
-                    current_state.state = GameState::Playing;
+                    current_state.set(GameState::Playing);
                     for (mut text, _infoboard) in &mut infoboard_query.iter() {
                         text.value = format!("");
                     }
                 }
             },
             GameState::Playing => {
+                let speed = levels.for_mode(*mode).ball_speed;
                 if body.linvel.x > 0.0 {
-                    body.linvel.x = 30.0;
+                    body.linvel.x = speed;
                 } else {
-                    body.linvel.x = -30.0;
+                    body.linvel.x = -speed;
                 }
                 if body.linvel.z > 0.0 {
-                    body.linvel.z = 30.0;
+                    body.linvel.z = speed;
                 } else {
-                    body.linvel.z = -30.0;
+                    body.linvel.z = -speed;
                 }
                 if body.linvel.y > 0.0 {
-                    body.linvel.y = -30.0;
+                    body.linvel.y = -speed;
                 }
 
-                // Off the screen
+                // Off the screen, past the bottom paddle or in versus the top one
+                let mut lost_by = None;
                 if body.position.translation.z < -60.0 {
-                    current_state.state = GameState::GameOver;
+                    lost_by = Some(0);
+                } else if *mode == GameMode::Versus && body.position.translation.z > 60.0 {
+                    lost_by = Some(1);
+                }
+
+                if let Some(player) = lost_by {
+                    if mode.has_lives() {
+                        lives.count[player] = lives.count[player].saturating_sub(1);
+                    }
+                    if lives.count[player] == 0 {
+                        current_state.set(GameState::GameOver);
+                    } else {
+                        current_state.set(GameState::ArenaStart);
+                    }
+
+                    // The next serve goes towards whoever just lost the ball
+                    if *mode == GameMode::Versus {
+                        let z = if player == 0 { -1.0 } else { 1.0 };
+                        ball.velocity = Vec3::new(ball.velocity.x(), 0.0, z).normalize();
+                    }
+                    ball_lost_events.send(BallLost { player });
                 }
             },
             _ => (),
@@ -557,42 +969,102 @@ fn ball_movement_system(
     }
 }
 
+fn paddle_input_system(
+    attract: Res<Attract>,
+    keyboard_input: Res<Input<KeyCode>>,
+    mut paddle_input: ResMut<PaddleInput>,
+) {
+    // The demo drives the paddle itself while attract mode is running
+    if attract.active {
+        return;
+    }
+
+    // Arrow keys for the first player, A and D for the second
+    let layouts = [(KeyCode::Left, KeyCode::Right), (KeyCode::A, KeyCode::D)];
+    for (player, (left, right)) in layouts.iter().enumerate() {
+        let mut direction = 0.0;
+        if keyboard_input.pressed(*left) {
+            direction += 1.0;
+        }
+
+        if keyboard_input.pressed(*right) {
+            direction -= 1.0;
+        }
+
+        paddle_input.direction[player] = direction;
+    }
+    paddle_input.serve = keyboard_input.pressed(KeyCode::Space);
+}
+
+fn run_timer_system(
+    time: Res<Time>,
+    current_state: Res<CurrentState>,
+    mut run_timer: ResMut<RunTimer>,
+) {
+    if current_state.state == GameState::Playing {
+        run_timer.seconds += time.delta_seconds;
+    }
+}
+
+fn pause_system(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut current_state: ResMut<CurrentState>,
+) {
+    if keyboard_input.just_pressed(KeyCode::P) {
+        match current_state.state {
+            GameState::Playing => current_state.set(GameState::Paused),
+            GameState::Paused => current_state.set(GameState::Playing),
+            _ => (),
+        };
+    }
+}
+
 fn paddle_movement_system(
     current_state: Res<CurrentState>,
     time: Res<Time>,
-    keyboard_input: Res<Input<KeyCode>>,
-    player: Res<PlayerEntity>,
+    paddle_input: Res<PaddleInput>,
     mut bodies: ResMut<RigidBodySet>,
-    mut query: Query<(&ColliderHandleComponent, &RigidBodyHandleComponent, &Paddle)>,
+    mut query: Query<(&RigidBodyHandleComponent, &Paddle)>,
 ) {
     match current_state.state {
         GameState::ArenaStart => {
-            if let Ok(body_handle) = query.get::<RigidBodyHandleComponent>(player.0) {
+            for (body_handle, paddle) in &mut query.iter() {
                 let mut body = bodies.get_mut(body_handle.handle()).unwrap();
-                let paddle = query.get::<Paddle>(player.0).unwrap();
 
-                body.position.translation.x = 0.0;
+                body.position.translation.x = paddle.home_x;
                 body.position.translation.y = 3.0;
-                body.position.translation.z = -35.0;
+                body.position.translation.z = paddle.home_z;
             }
         },
         GameState::Playing => {
-            let mut direction = 0.0;
-            if keyboard_input.pressed(KeyCode::Left) {
-                direction += 1.0;
-            }
-
-            if keyboard_input.pressed(KeyCode::Right) {
-                direction -= 1.0;
+            // Kinematic bodies pass straight through each other, so paddles
+            // sharing an edge are kept apart by hand
+            let mut positions = vec![];
+            for (body_handle, paddle) in &mut query.iter() {
+                let body = bodies.get(body_handle.handle()).unwrap();
+                positions.push((paddle.player, paddle.home_z, body.position.translation.x));
             }
 
-            if let Ok(body_handle) = query.get::<RigidBodyHandleComponent>(player.0) {
+            for (body_handle, paddle) in &mut query.iter() {
+                let direction = paddle_input.direction[paddle.player];
                 let mut body = bodies.get_mut(body_handle.handle()).unwrap();
-                let paddle = query.get::<Paddle>(player.0).unwrap();
 
                 // Kinematic Move
+                let mut min_x = paddle.min_x;
+                let mut max_x = paddle.max_x;
+                for &(player, home_z, x) in positions.iter() {
+                    if player == paddle.player || home_z != paddle.home_z {
+                        continue;
+                    }
+                    if x > body.position.translation.x {
+                        max_x = f32::min(max_x, x - 2.0 * PADDLE_HALF_WIDTH);
+                    } else {
+                        min_x = f32::max(min_x, x + 2.0 * PADDLE_HALF_WIDTH);
+                    }
+                }
+
                 let mut x_trans = body.position.translation.x + time.delta_seconds * direction * paddle.speed;
-                x_trans = f32::max(-25.5, f32::min(25.5, x_trans));
+                x_trans = f32::max(min_x, f32::min(max_x, x_trans));
 
                 let translation = Translation3::new(x_trans, body.position.translation.y, body.position.translation.z);
                 let rotation = UnitQuaternion::from_scaled_axis(Vector3::y() * PI);
@@ -607,12 +1079,38 @@ fn paddle_movement_system(
 
 fn infoboard_system(
     current_state: Res<CurrentState>,
+    attract: Res<Attract>,
+    editor: Res<Editor>,
+    daily: Res<Daily>,
+    time_attack: Res<TimeAttack>,
+    levels: Res<Levels>,
+    high_scores: Res<HighScores>,
+    survival_high_scores: Res<SurvivalHighScores>,
+    mode: Res<GameMode>,
+    match_result: Res<MatchResult>,
     mut query: Query<(&mut Text, &Infoboard)>,
     ) {
+        if attract.active {
+            for (mut text, _infoboard) in &mut query.iter() {
+                text.value = attract.banner(&high_scores);
+            }
+            return;
+        }
+
         match current_state.state {
             GameState::ArenaStart => {
                 for (mut text, _infoboard) in &mut query.iter() {
-                    text.value = "Press SPACE to Start".to_string();
+                    text.value = if editor.playtesting {
+                        "PLAYTEST - Press SPACE to Start, E to Edit".to_string()
+                    } else if *mode == GameMode::Daily {
+                        daily.banner() + &modes::start_banner(*mode, &match_result)
+                    } else if *mode == GameMode::TimeAttack {
+                        time_attack.banner(&levels) + &modes::start_banner(*mode, &match_result)
+                    } else if *mode == GameMode::Survival {
+                        survival_high_scores.0.table() + "\n" + &modes::start_banner(*mode, &match_result)
+                    } else {
+                        modes::start_banner(*mode, &match_result)
+                    };
                 }
             },
             GameState::Playing => {
@@ -620,24 +1118,9 @@ fn infoboard_system(
                     text.value = "".to_string();
                 }
             },
-            _ => (),
-        };
-}
-
-fn scoreboard_system(
-    current_state: Res<CurrentState>,
-    scoreboard: Res<Scoreboard>,
-    mut query: Query<(&mut Text, &Scoreboard)>
-    ) {
-        match current_state.state {
-            GameState::ArenaStart => {
-                for (mut text, _scoreboard_component) in &mut query.iter() {
-                    text.value = format!("");
-                }
-            },
-            GameState::Playing => {
-                for (mut text, _scoreboard_component) in &mut query.iter() {
-                    text.value = format!("Score: {}", scoreboard.score);
+            GameState::Paused => {
+                for (mut text, _infoboard) in &mut query.iter() {
+                    text.value = "PAUSED - Press P to Resume".to_string();
                 }
             },
             _ => (),
@@ -0,0 +1,227 @@
+use bevy::prelude::*;
+use bevy_rapier3d::physics::RigidBodyHandleComponent;
+use bevy_rapier3d::rapier::dynamics::{RigidBodySet, JointSet};
+use bevy_rapier3d::rapier::geometry::{BroadPhase, NarrowPhase, ColliderSet};
+use bevy_rapier3d::rapier::pipeline::PhysicsPipeline;
+use crate::{
+    CurrentState, GameState, Lives, Paddle, Scoreboard, TopWallEntity, MAX_PLAYERS, PADDLE_HALF_WIDTH,
+    PADDLE_MAX_X, START_LIVES,
+};
+use crate::attract::Attract;
+use crate::level::ResetLevel;
+use crate::settings::Settings;
+
+const BOTTOM_PADDLE_Z: f32 = -35.0;
+// The second player's paddle takes the top wall's place in versus
+const TOP_PADDLE_Z: f32 = 35.0;
+// Co-op paddles start out in the middle of their own half
+const COOP_HOME_X: f32 = 14.0;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum GameMode {
+    Classic,
+    Versus,
+    Coop,
+    Endless,
+    Daily,
+    TimeAttack,
+    Survival,
+}
+
+impl GameMode {
+    fn name(self) -> &'static str {
+        match self {
+            GameMode::Classic => "Classic",
+            GameMode::Versus => "Versus",
+            GameMode::Coop => "Co-op",
+            GameMode::Endless => "Endless",
+            GameMode::Daily => "Daily",
+            GameMode::TimeAttack => "Time attack",
+            GameMode::Survival => "Survival",
+        }
+    }
+
+    // Losing the ball in time attack costs time instead
+    pub fn has_lives(self) -> bool {
+        self != GameMode::TimeAttack
+    }
+
+    // Modes that play the campaign's levels, which the editor works on
+    pub fn uses_campaign(self) -> bool {
+        self == GameMode::Classic || self == GameMode::Coop
+    }
+
+    fn has_top_wall(self) -> bool {
+        self != GameMode::Versus
+    }
+
+    // The paddle each player gets in this mode, if they play at all. Screen
+    // left is +x, so the arrow keys player takes the -x half in co-op.
+    fn paddle(self, player: usize, split_halves: bool) -> Option<Paddle> {
+        match (self, player) {
+            (GameMode::Versus, 1) => Some(Paddle::new(1, TOP_PADDLE_Z)),
+            (GameMode::Coop, _) => {
+                let side = if player == 0 { -1.0 } else { 1.0 };
+                let mut paddle = Paddle::new(player, BOTTOM_PADDLE_Z);
+                paddle.home_x = side * COOP_HOME_X;
+                if split_halves {
+                    // Both paddles reach the centre line so there's no gap between them
+                    let inner = side * PADDLE_HALF_WIDTH;
+                    let outer = side * PADDLE_MAX_X;
+                    paddle.min_x = f32::min(inner, outer);
+                    paddle.max_x = f32::max(inner, outer);
+                }
+                Some(paddle)
+            },
+            // Every other mode is single player along the bottom edge
+            (_, 0) => Some(Paddle::new(0, BOTTOM_PADDLE_Z)),
+            _ => None,
+        }
+    }
+
+    // Versus serves from the centre line so neither player starts closer
+    pub fn serve_z(self) -> f32 {
+        match self {
+            GameMode::Versus => 0.0,
+            _ => -20.0,
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct MatchResult {
+    pub winner: Option<usize>,
+}
+
+pub fn start_banner(mode: GameMode, match_result: &MatchResult) -> String {
+    let mut banner = String::new();
+    if let Some(winner) = match_result.winner {
+        banner += &format!("PLAYER {} WINS\n", winner + 1);
+    }
+    banner += &format!("Mode: {} (1 Classic, 2 Versus, 3 Co-op, 4 Endless, 5 Daily, 6 Time attack, 7 Survival)\n", mode.name());
+    if mode.uses_campaign() {
+        banner += "E Level Editor\n";
+    }
+    banner += "Press SPACE to Start";
+    banner
+}
+
+pub fn mode_select_system(
+    keyboard_input: Res<Input<KeyCode>>,
+    attract: Res<Attract>,
+    current_state: Res<CurrentState>,
+    mut mode: ResMut<GameMode>,
+    mut scoreboard: ResMut<Scoreboard>,
+    mut lives: ResMut<Lives>,
+    mut match_result: ResMut<MatchResult>,
+    mut reset_events: ResMut<Events<ResetLevel>>,
+) {
+    if current_state.state == GameState::Playing {
+        match_result.winner = None;
+    }
+
+    if attract.active || current_state.state != GameState::ArenaStart {
+        return;
+    }
+
+    let mut selected = None;
+    if keyboard_input.just_pressed(KeyCode::Key1) {
+        selected = Some(GameMode::Classic);
+    }
+    if keyboard_input.just_pressed(KeyCode::Key2) {
+        selected = Some(GameMode::Versus);
+    }
+    if keyboard_input.just_pressed(KeyCode::Key3) {
+        selected = Some(GameMode::Coop);
+    }
+    if keyboard_input.just_pressed(KeyCode::Key4) {
+        selected = Some(GameMode::Endless);
+    }
+    if keyboard_input.just_pressed(KeyCode::Key5) {
+        selected = Some(GameMode::Daily);
+    }
+    if keyboard_input.just_pressed(KeyCode::Key6) {
+        selected = Some(GameMode::TimeAttack);
+    }
+    if keyboard_input.just_pressed(KeyCode::Key7) {
+        selected = Some(GameMode::Survival);
+    }
+
+    if let Some(selected) = selected {
+        if selected != *mode {
+            *mode = selected;
+            scoreboard.reset();
+            lives.count = [START_LIVES; MAX_PLAYERS];
+            match_result.winner = None;
+            reset_events.send(ResetLevel);
+        }
+    }
+}
+
+// Rebuilds the paddles for the new mode and swaps the top wall for the second
+// player's paddle and back when versus starts or ends
+pub fn mode_arena_system(
+    mut commands: Commands,
+    mode: Res<GameMode>,
+    settings: Res<Settings>,
+    top_wall: Res<TopWallEntity>,
+    mut applied: Local<Option<GameMode>>,
+    mut pipeline: ResMut<PhysicsPipeline>,
+    mut broad_phase: ResMut<BroadPhase>,
+    mut narrow_phase: ResMut<NarrowPhase>,
+    mut bodies: ResMut<RigidBodySet>,
+    mut colliders: ResMut<ColliderSet>,
+    mut joints: ResMut<JointSet>,
+    handles: Query<&RigidBodyHandleComponent>,
+    mut paddles: Query<(Entity, Mut<Paddle>, &RigidBodyHandleComponent)>,
+) {
+    // The arena always starts out with the single player layout
+    let previous = applied.unwrap_or(GameMode::Classic);
+    *applied = Some(*mode);
+    if previous == *mode {
+        return;
+    }
+
+    // The first player's paddle stays put and only changes its limits; any
+    // other paddles are rebuilt from scratch
+    for (entity, mut paddle, handle) in &mut paddles.iter() {
+        if paddle.player == 0 {
+            if let Some(layout) = mode.paddle(0, settings.coop_split_halves) {
+                *paddle = layout;
+            }
+            continue;
+        }
+        pipeline.remove_rigid_body(
+            handle.handle(),
+            &mut broad_phase,
+            &mut narrow_phase,
+            &mut bodies,
+            &mut colliders,
+            &mut joints,
+        );
+        commands.despawn(entity);
+    }
+
+    for player in 1..MAX_PLAYERS {
+        if let Some(layout) = mode.paddle(player, settings.coop_split_halves) {
+            crate::spawn_paddle(&mut commands, layout);
+        }
+    }
+
+    if previous.has_top_wall() && !mode.has_top_wall() {
+        if let Ok(handle) = handles.get::<RigidBodyHandleComponent>(top_wall.0) {
+            pipeline.remove_rigid_body(
+                handle.handle(),
+                &mut broad_phase,
+                &mut narrow_phase,
+                &mut bodies,
+                &mut colliders,
+                &mut joints,
+            );
+        }
+        commands.despawn(top_wall.0);
+    } else if !previous.has_top_wall() && mode.has_top_wall() {
+        let top_wall_entity = crate::spawn_top_wall(&mut commands);
+        commands.insert_resource(TopWallEntity(top_wall_entity));
+    }
+}
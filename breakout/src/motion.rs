@@ -0,0 +1,112 @@
+use bevy::prelude::*;
+use bevy_rapier3d::na::{Isometry3, Translation3};
+use bevy_rapier3d::physics::RigidBodyHandleComponent;
+use bevy_rapier3d::rapier::dynamics::{IntegrationParameters, RigidBodySet};
+use serde::{Deserialize, Serialize};
+
+// How a block moves in the level file. Speeds are in units per second, and
+// every pattern starts from the block's place so the editor grid still shows
+// where it is.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "pattern", rename_all = "snake_case")]
+pub enum BlockMotion {
+    // Side to side, up to `distance` either way
+    Slide { distance: f32, speed: f32 },
+    // Round a circle whose centre is `radius` to the left of the block, as
+    // seen from the bottom paddle
+    Orbit { radius: f32, speed: f32 },
+    // Through each [x, z] waypoint, relative to the block's place, and back
+    Patrol { waypoints: Vec<[f32; 2]>, speed: f32 },
+}
+
+impl BlockMotion {
+    // Offset from the block's place after this long on the move
+    pub fn offset(&self, seconds: f32) -> (f32, f32) {
+        match self {
+            BlockMotion::Slide { distance, speed } => {
+                if *distance <= 0.0 {
+                    return (0.0, 0.0);
+                }
+                (distance * (seconds * speed / distance).sin(), 0.0)
+            },
+            BlockMotion::Orbit { radius, speed } => {
+                if *radius <= 0.0 {
+                    return (0.0, 0.0);
+                }
+                let angle = seconds * speed / radius;
+                (radius * (1.0 - angle.cos()), radius * angle.sin())
+            },
+            BlockMotion::Patrol { waypoints, speed } => {
+                let mut points = vec![[0.0, 0.0]];
+                points.extend(waypoints.iter().cloned());
+                let legs: Vec<([f32; 2], [f32; 2], f32)> = (0..points.len())
+                    .map(|index| {
+                        let (from, to) = (points[index], points[(index + 1) % points.len()]);
+                        (from, to, (to[0] - from[0]).hypot(to[1] - from[1]))
+                    })
+                    .collect();
+
+                let total: f32 = legs.iter().map(|leg| leg.2).sum();
+                if total <= 0.0 {
+                    return (0.0, 0.0);
+                }
+                let mut travelled = (seconds * speed).rem_euclid(total);
+                for (from, to, length) in legs {
+                    if travelled <= length && length > 0.0 {
+                        let t = travelled / length;
+                        return (from[0] + (to[0] - from[0]) * t, from[1] + (to[1] - from[1]) * t);
+                    }
+                    travelled -= length;
+                }
+                (0.0, 0.0)
+            },
+        }
+    }
+}
+
+// Cloned into netplay snapshots, clock and all, so a rolled back block picks
+// up its pattern where it was
+#[derive(Clone)]
+pub struct MovingBlock {
+    motion: BlockMotion,
+    // The block's place in the level
+    x: f32,
+    z: f32,
+    seconds: f32,
+}
+
+impl MovingBlock {
+    pub fn new(motion: BlockMotion, x: f32, z: f32) -> Self {
+        MovingBlock {
+            motion,
+            x,
+            z,
+            seconds: 0.0,
+        }
+    }
+
+    pub fn motion(&self) -> &BlockMotion {
+        &self.motion
+    }
+}
+
+// Moving blocks are kinematic, so they're told where to be after the next
+// step and the ball bounces off them like any other body. Their clock runs on
+// the physics step, which stops them along with everything else on pause, in
+// the editor and during hit stop.
+pub fn moving_block_system(
+    integration_parameters: Res<IntegrationParameters>,
+    mut bodies: ResMut<RigidBodySet>,
+    mut query: Query<(Mut<MovingBlock>, &RigidBodyHandleComponent)>,
+) {
+    let dt = integration_parameters.dt();
+    for (mut moving, handle) in &mut query.iter() {
+        moving.seconds += dt;
+        let (x, z) = moving.motion.offset(moving.seconds);
+        if let Some(mut body) = bodies.get_mut(handle.handle()) {
+            let translation = Translation3::new(moving.x + x, body.position.translation.y, moving.z + z);
+            let isometry = Isometry3::from_parts(translation, body.position.rotation);
+            body.set_next_kinematic_position(isometry);
+        }
+    }
+}
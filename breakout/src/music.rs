@@ -0,0 +1,173 @@
+use std::fs;
+use std::io::Cursor;
+use bevy::prelude::*;
+use rodio::{Decoder, Sink, Source};
+use crate::{GameState, StateChanged};
+use crate::attract::Attract;
+use crate::audio::AudioBackend;
+use crate::editor::Editor;
+use crate::level::{Levels, ResetLevel};
+use crate::modes::GameMode;
+use crate::settings::Settings;
+
+// Tracks are looked up as assets/music/<name>.mp3; missing tracks are silent
+const MENU_TRACK: &str = "menu";
+const GAME_OVER_TRACK: &str = "game_over";
+// Tracks a level can pick, in the editor or from the generator
+pub const LEVEL_TRACKS: [&str; 4] = ["classic", "pyramid", "checkerboard", "versus"];
+
+const CROSSFADE_SECONDS: f32 = 1.5;
+const DUCKED_VOLUME: f32 = 0.3;
+
+struct Channel {
+    track: String,
+    sink: Option<Sink>,
+    fade: f32,
+}
+
+impl Channel {
+    fn open(backend: &AudioBackend, track: &str) -> Self {
+        let sink = match backend {
+            AudioBackend::Device(device) => {
+                let path = format!("assets/music/{}.mp3", track);
+                fs::read(&path)
+                    .ok()
+                    .and_then(|bytes| Decoder::new(Cursor::new(bytes)).ok())
+                    .map(|decoder| {
+                        let sink = Sink::new(device);
+                        sink.set_volume(0.0);
+                        sink.append(decoder.repeat_infinite());
+                        sink
+                    })
+            },
+            AudioBackend::Null => None,
+        };
+
+        Channel {
+            track: track.to_string(),
+            sink,
+            fade: 0.0,
+        }
+    }
+
+    fn set_volume(&self, volume: f32) {
+        if let Some(sink) = &self.sink {
+            sink.set_volume(volume);
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct MusicPlayer {
+    current: Option<Channel>,
+    fading_out: Vec<Channel>,
+    ducked: bool,
+    // The game over state only lasts a frame before the arena is set up
+    // again, so its track plays on until the next serve
+    game_over: bool,
+}
+
+impl MusicPlayer {
+    fn switch_to(&mut self, backend: &AudioBackend, track: Option<String>) {
+        let current_track = self.current.as_ref().map(|channel| channel.track.clone());
+        if current_track == track {
+            return;
+        }
+
+        if let Some(channel) = self.current.take() {
+            self.fading_out.push(channel);
+        }
+        self.current = track.map(|track| Channel::open(backend, &track));
+    }
+}
+
+fn wanted_track(attract: &Attract, game_over: bool, levels: &Levels, mode: GameMode) -> Option<String> {
+    if attract.active {
+        return Some(MENU_TRACK.to_string());
+    }
+
+    if game_over {
+        Some(GAME_OVER_TRACK.to_string())
+    } else {
+        levels.for_mode(mode).music.clone()
+    }
+}
+
+pub fn music_event_system(
+    backend: Res<AudioBackend>,
+    attract: Res<Attract>,
+    levels: Res<Levels>,
+    mode: Res<GameMode>,
+    mut player: ResMut<MusicPlayer>,
+    mut started: Local<bool>,
+    mut state_reader: Local<EventReader<StateChanged>>,
+    state_events: Res<Events<StateChanged>>,
+    mut reset_reader: Local<EventReader<ResetLevel>>,
+    reset_events: Res<Events<ResetLevel>>,
+) {
+    let mut changed = !*started;
+    *started = true;
+
+    for transition in state_reader.iter(&state_events) {
+        player.ducked = transition.to == GameState::Paused;
+        match transition.to {
+            GameState::GameOver => player.game_over = true,
+            GameState::Playing | GameState::Editing => player.game_over = false,
+            _ => (),
+        }
+        // The demo ending its run isn't a real game over
+        if attract.active {
+            player.game_over = false;
+        }
+        changed = true;
+    }
+
+    if reset_reader.iter(&reset_events).last().is_some() {
+        changed = true;
+    }
+
+    if changed {
+        let track = wanted_track(&attract, player.game_over, &levels, *mode);
+        player.switch_to(&backend, track);
+    }
+}
+
+pub fn music_fade_system(
+    time: Res<Time>,
+    settings: Res<Settings>,
+    mut player: ResMut<MusicPlayer>,
+) {
+    let step = time.delta_seconds / CROSSFADE_SECONDS;
+    let mut volume = settings.music_volume;
+    if settings.music_muted {
+        volume = 0.0;
+    }
+    if player.ducked {
+        volume *= DUCKED_VOLUME;
+    }
+
+    if let Some(channel) = &mut player.current {
+        channel.fade = f32::min(1.0, channel.fade + step);
+        channel.set_volume(channel.fade * volume);
+    }
+
+    for channel in player.fading_out.iter_mut() {
+        channel.fade = f32::max(0.0, channel.fade - step);
+        channel.set_volume(channel.fade * volume);
+    }
+
+    // Dropping a finished channel's sink stops its playback
+    player.fading_out.retain(|channel| channel.fade > 0.0);
+}
+
+pub fn music_mute_system(
+    keyboard_input: Res<Input<KeyCode>>,
+    editor: Res<Editor>,
+    mut settings: ResMut<Settings>,
+) {
+    // M is just a letter while a level name is being typed
+    if keyboard_input.just_pressed(KeyCode::M) && !editor.naming {
+        settings.music_muted = !settings.music_muted;
+        settings.save();
+    }
+}
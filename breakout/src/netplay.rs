@@ -0,0 +1,1227 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::env::current_exe;
+use std::io;
+use std::net::{SocketAddr, UdpSocket};
+use std::process::{self, Command};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use bevy::prelude::*;
+use bevy_rapier3d::na::{Isometry3, Vector3};
+use bevy_rapier3d::physics::{ColliderHandleComponent, EventQueue, RigidBodyHandleComponent};
+use bevy_rapier3d::rapier::dynamics::{IntegrationParameters, RigidBodyHandle, RigidBodySet, JointSet};
+use bevy_rapier3d::rapier::geometry::{BroadPhase, NarrowPhase, ColliderSet};
+use bevy_rapier3d::rapier::pipeline::PhysicsPipeline;
+use crate::{
+    Ball, BallEntity, BallLost, Block, BlockDestroyed, BodyHandleToEntity, Contacts, CurrentState, GameState,
+    Infoboard, Lives, Model, Paddle, PaddleInput, RunTimer, Scoreboard, StateChanged, MAX_PLAYERS, START_LIVES,
+};
+use crate::env::{self, STEP_SECONDS};
+use crate::highscores::HighScores;
+use crate::hud;
+use crate::level::{self, BlockKind, BlockSpec, Levels, ResetLevel};
+use crate::modes::GameMode;
+use crate::motion::MovingBlock;
+use crate::powerups::ActivePowerUps;
+use crate::rng::Rng;
+use crate::scoring::{Combo, LevelCleared, PointsAwarded};
+use crate::spectate;
+use crate::survival::{self, SurvivalHighScores};
+use crate::time_attack::TimeAttack;
+use crate::window;
+
+// How far the simulation may run ahead of the last input heard from the peer
+const MAX_PREDICTION_FRAMES: u32 = 8;
+// Every packet repeats all inputs the peer hasn't acknowledged, up to this many
+const MAX_INPUTS_PER_PACKET: usize = 64;
+// Checksums are kept around this long for comparing against the peer's
+const CHECKSUM_HISTORY_FRAMES: u32 = 600;
+const PEER_TIMEOUT: Duration = Duration::from_secs(5);
+// The other player gets longer than that to start their side
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(60);
+// Keep answering for a moment after finishing so the peer can finish too
+const LINGER_FRAMES: usize = 60;
+
+const PACKET_MAGIC: &[u8; 2] = b"BN";
+const PACKET_HEADER_LEN: usize = 23;
+const NO_CHECKSUM: u32 = u32::MAX;
+
+const LOOPBACK_PORTS: [u16; 2] = [7400, 7401];
+
+#[derive(Clone, Copy, PartialEq, Default)]
+struct NetInput {
+    // -1, 0 or 1, the same as PaddleInput's direction
+    direction: i8,
+    serve: bool,
+}
+
+impl NetInput {
+    fn encode(self) -> u8 {
+        let direction = match self.direction {
+            1 => 1,
+            -1 => 2,
+            _ => 0,
+        };
+        direction | (self.serve as u8) << 2
+    }
+
+    fn decode(byte: u8) -> NetInput {
+        NetInput {
+            direction: match byte & 0b11 {
+                1 => 1,
+                2 => -1,
+                _ => 0,
+            },
+            serve: byte & 0b100 != 0,
+        }
+    }
+}
+
+// Little endian on the wire:
+//   magic [2] | start frame u32 | ack u32 | checksum frame u32 | checksum u64
+//   | input count u8 | one byte per input
+struct Packet {
+    start_frame: u32,
+    // How many of the receiver's inputs the sender has so far
+    ack: u32,
+    checksum: Option<(u32, u64)>,
+    inputs: Vec<NetInput>,
+}
+
+impl Packet {
+    fn encode(&self) -> Vec<u8> {
+        let (checksum_frame, checksum) = self.checksum.unwrap_or((NO_CHECKSUM, 0));
+        let mut bytes = Vec::with_capacity(PACKET_HEADER_LEN + self.inputs.len());
+        bytes.extend_from_slice(PACKET_MAGIC);
+        bytes.extend_from_slice(&self.start_frame.to_le_bytes());
+        bytes.extend_from_slice(&self.ack.to_le_bytes());
+        bytes.extend_from_slice(&checksum_frame.to_le_bytes());
+        bytes.extend_from_slice(&checksum.to_le_bytes());
+        bytes.push(self.inputs.len() as u8);
+        bytes.extend(self.inputs.iter().map(|input| input.encode()));
+        bytes
+    }
+
+    fn decode(bytes: &[u8]) -> Option<Packet> {
+        if bytes.len() < PACKET_HEADER_LEN || &bytes[0..2] != PACKET_MAGIC {
+            return None;
+        }
+
+        let u32_at = |at: usize| {
+            let mut word = [0; 4];
+            word.copy_from_slice(&bytes[at..at + 4]);
+            u32::from_le_bytes(word)
+        };
+        let mut checksum = [0; 8];
+        checksum.copy_from_slice(&bytes[14..22]);
+
+        let count = bytes[22] as usize;
+        if bytes.len() != PACKET_HEADER_LEN + count {
+            return None;
+        }
+
+        let checksum_frame = u32_at(10);
+        Some(Packet {
+            start_frame: u32_at(2),
+            ack: u32_at(6),
+            checksum: if checksum_frame == NO_CHECKSUM {
+                None
+            } else {
+                Some((checksum_frame, u64::from_le_bytes(checksum)))
+            },
+            inputs: bytes[PACKET_HEADER_LEN..].iter().map(|&byte| NetInput::decode(byte)).collect(),
+        })
+    }
+}
+
+trait Transport {
+    fn send(&mut self, bytes: &[u8]);
+    fn recv(&mut self) -> Option<Vec<u8>>;
+}
+
+struct UdpTransport {
+    socket: UdpSocket,
+    peer: SocketAddr,
+}
+
+impl UdpTransport {
+    fn bind(local: &str, peer: &str) -> io::Result<Self> {
+        let socket = UdpSocket::bind(local)?;
+        socket.set_nonblocking(true)?;
+        let peer = peer
+            .parse()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        Ok(UdpTransport { socket, peer })
+    }
+}
+
+impl Transport for UdpTransport {
+    fn send(&mut self, bytes: &[u8]) {
+        // Lost packets are expected; the next one repeats everything anyway
+        let _ = self.socket.send_to(bytes, self.peer);
+    }
+
+    fn recv(&mut self) -> Option<Vec<u8>> {
+        let mut buffer = [0; 512];
+        loop {
+            match self.socket.recv_from(&mut buffer) {
+                Ok((len, from)) if from == self.peer => return Some(buffer[..len].to_vec()),
+                Ok(_) => continue,
+                // Would block, or an ICMP error left over from a peer that
+                // isn't listening yet
+                Err(_) => return None,
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+pub struct LinkConditions {
+    pub latency_ms: u64,
+    pub loss_percent: f32,
+}
+
+impl LinkConditions {
+    // --net-latency <ms> and --net-loss <percent>, both optional
+    pub fn from_args(args: &[String]) -> Self {
+        let value_after = |flag: &str| {
+            args.iter()
+                .position(|arg| arg == flag)
+                .and_then(|position| args.get(position + 1))
+        };
+        LinkConditions {
+            latency_ms: value_after("--net-latency").and_then(|arg| arg.parse().ok()).unwrap_or(0),
+            loss_percent: value_after("--net-loss").and_then(|arg| arg.parse().ok()).unwrap_or(0.0),
+        }
+    }
+}
+
+// Stand-in for a real network: holds outgoing packets back by the latency,
+// with up to a quarter of it again as jitter, and drops some of them
+struct SimulatedLink<T: Transport> {
+    inner: T,
+    conditions: LinkConditions,
+    rng: Rng,
+    in_flight: Vec<(Instant, Vec<u8>)>,
+}
+
+impl<T: Transport> SimulatedLink<T> {
+    fn flush(&mut self) {
+        let now = Instant::now();
+        let inner = &mut self.inner;
+        self.in_flight.retain(|(due, bytes)| {
+            if *due <= now {
+                inner.send(bytes);
+                false
+            } else {
+                true
+            }
+        });
+    }
+}
+
+impl<T: Transport> Transport for SimulatedLink<T> {
+    fn send(&mut self, bytes: &[u8]) {
+        if !self.rng.chance(self.conditions.loss_percent / 100.0) {
+            let latency = self.conditions.latency_ms as f32;
+            let delay = latency + self.rng.range(0.0, latency / 4.0);
+            let due = Instant::now() + Duration::from_secs_f32(delay / 1000.0);
+            self.in_flight.push((due, bytes.to_vec()));
+        }
+        self.flush();
+    }
+
+    fn recv(&mut self) -> Option<Vec<u8>> {
+        self.flush();
+        self.inner.recv()
+    }
+}
+
+#[derive(Clone, Copy)]
+struct BodyState {
+    position: Isometry3<f32>,
+    linvel: Vector3<f32>,
+    angvel: Vector3<f32>,
+}
+
+struct BlockState {
+    entity: Entity,
+    body: BodyState,
+    color: Color,
+    kind: BlockKind,
+    hits_left: usize,
+    motion: Option<MovingBlock>,
+}
+
+// Everything the simulation depends on, taken between two frames, apart from
+// rapier's contact pairs. Restoring rebuilds the bodies so their contacts are
+// found again from scratch, and sessions run the solver without warm starting
+// so no impulses are carried between frames either. That still isn't exact: a
+// pair already touching at the snapshot reports a fresh contact on the first
+// resimulated step, which the peer's timeline never saw. The checksums catch
+// it when that changes the outcome.
+struct Snapshot {
+    bodies: Vec<(Entity, BodyState)>,
+    blocks: Vec<BlockState>,
+    ball_velocity: Vec3,
+    scoreboard: Scoreboard,
+    lives: [usize; MAX_PLAYERS],
+    state: GameState,
+    combo: Combo,
+}
+
+#[derive(Default)]
+struct Stats {
+    rollbacks: usize,
+    resimulated_frames: usize,
+    max_rollback_frames: u32,
+    stalled_ticks: usize,
+    checksums_verified: usize,
+}
+
+struct Desync {
+    frame: u32,
+    local: u64,
+    remote: u64,
+}
+
+struct Session {
+    app: App,
+    local_player: usize,
+    // The next frame to simulate
+    frame: u32,
+    local_inputs: Vec<NetInput>,
+    // Confirmed inputs from the peer, contiguous from frame 0
+    remote_inputs: Vec<NetInput>,
+    // What was assumed for the peer on frames simulated ahead of their input
+    predictions: HashMap<u32, NetInput>,
+    rollback_to: Option<u32>,
+    // State at the start of each frame that might still be rolled back
+    snapshots: HashMap<u32, Snapshot>,
+    // State at the end of each frame
+    checksums: HashMap<u32, u64>,
+    remote_checksums: Vec<(u32, u64)>,
+    peer_ack: u32,
+    stats: Stats,
+}
+
+impl Session {
+    fn new(mut app: App, local_player: usize) -> Self {
+        app.resources.get_mut::<IntegrationParameters>().unwrap().warmstart_coeff = 0.0;
+        Session {
+            app,
+            local_player,
+            frame: 0,
+            local_inputs: vec![],
+            remote_inputs: vec![],
+            predictions: HashMap::new(),
+            rollback_to: None,
+            snapshots: HashMap::new(),
+            checksums: HashMap::new(),
+            remote_checksums: vec![],
+            peer_ack: 0,
+            stats: Stats::default(),
+        }
+    }
+
+    fn confirmed_frames(&self) -> u32 {
+        u32::min(self.remote_inputs.len() as u32, self.frame)
+    }
+
+    // The peer's input for a frame, or a guess that they kept doing whatever
+    // they did last
+    fn remote_input(&self, frame: u32) -> NetInput {
+        match self.remote_inputs.get(frame as usize) {
+            Some(input) => *input,
+            None => self.remote_inputs.last().copied().unwrap_or_default(),
+        }
+    }
+
+    fn receive(&mut self, packet: Packet) {
+        self.peer_ack = u32::max(self.peer_ack, packet.ack);
+
+        for (offset, input) in packet.inputs.into_iter().enumerate() {
+            let frame = packet.start_frame + offset as u32;
+            if frame as usize != self.remote_inputs.len() {
+                continue;
+            }
+            self.remote_inputs.push(input);
+
+            if let Some(predicted) = self.predictions.remove(&frame) {
+                if predicted != input {
+                    self.rollback_to = Some(self.rollback_to.map_or(frame, |to| u32::min(to, frame)));
+                }
+            }
+        }
+
+        if let Some(checksum) = packet.checksum {
+            self.remote_checksums.push(checksum);
+        }
+    }
+
+    fn packet(&self) -> Packet {
+        let start = usize::min(self.peer_ack as usize, self.local_inputs.len());
+        let end = usize::min(self.local_inputs.len(), start + MAX_INPUTS_PER_PACKET);
+
+        let confirmed = self.confirmed_frames();
+        let checksum = if confirmed > 0 {
+            self.checksums.get(&(confirmed - 1)).map(|checksum| (confirmed - 1, *checksum))
+        } else {
+            None
+        };
+
+        Packet {
+            start_frame: start as u32,
+            ack: self.remote_inputs.len() as u32,
+            checksum,
+            inputs: self.local_inputs[start..end].to_vec(),
+        }
+    }
+
+    fn can_advance(&self) -> bool {
+        self.frame < self.remote_inputs.len() as u32 + MAX_PREDICTION_FRAMES
+    }
+
+    fn advance(&mut self, input: NetInput) {
+        if self.local_inputs.len() as u32 == self.frame {
+            self.local_inputs.push(input);
+        }
+        self.simulate();
+    }
+
+    fn simulate(&mut self) {
+        let frame = self.frame;
+        let snapshot = self.capture();
+        self.snapshots.insert(frame, snapshot);
+
+        let local = self.local_inputs[frame as usize];
+        let remote = self.remote_input(frame);
+        if frame as usize >= self.remote_inputs.len() {
+            self.predictions.insert(frame, remote);
+        }
+
+        {
+            let mut paddle_input = self.app.resources.get_mut::<PaddleInput>().unwrap();
+            let remote_player = 1 - self.local_player;
+            paddle_input.direction[self.local_player] = local.direction as f32;
+            paddle_input.direction[remote_player] = remote.direction as f32;
+            paddle_input.serve = local.serve || remote.serve;
+        }
+        self.app.resources.get_mut::<Time>().unwrap().delta_seconds = STEP_SECONDS;
+        self.app.update();
+
+        let checksum = self.checksum();
+        self.checksums.insert(frame, checksum);
+        self.frame += 1;
+    }
+
+    fn rollback(&mut self) {
+        let to = match self.rollback_to.take() {
+            Some(to) if to < self.frame => to,
+            _ => return,
+        };
+
+        let end = self.frame;
+        let snapshot = self.snapshots.remove(&to).unwrap();
+        self.restore(&snapshot);
+        self.frame = to;
+        while self.frame < end {
+            self.simulate();
+        }
+
+        self.stats.rollbacks += 1;
+        self.stats.resimulated_frames += (end - to) as usize;
+        self.stats.max_rollback_frames = u32::max(self.stats.max_rollback_frames, end - to);
+    }
+
+    fn verify(&mut self) -> Result<(), Desync> {
+        let confirmed = self.confirmed_frames();
+        let mut pending = vec![];
+        for (frame, remote) in self.remote_checksums.drain(..) {
+            if frame >= confirmed {
+                pending.push((frame, remote));
+                continue;
+            }
+            if let Some(&local) = self.checksums.get(&frame) {
+                if local != remote {
+                    return Err(Desync { frame, local, remote });
+                }
+                self.stats.checksums_verified += 1;
+            }
+        }
+        self.remote_checksums = pending;
+        Ok(())
+    }
+
+    // Frames before the first unconfirmed one can never be rolled back to
+    fn prune(&mut self) {
+        let confirmed = self.confirmed_frames();
+        self.snapshots.retain(|frame, _| *frame >= confirmed);
+        let oldest = self.frame.saturating_sub(CHECKSUM_HISTORY_FRAMES);
+        self.checksums.retain(|frame, _| *frame >= oldest);
+    }
+
+    fn ball_body(&self) -> Option<(Entity, RigidBodyHandle)> {
+        let ball_entity = self.app.resources.get::<BallEntity>().unwrap().0;
+        let handle = self.app.world.get::<RigidBodyHandleComponent>(ball_entity).ok()?;
+        Some((ball_entity, handle.handle()))
+    }
+
+    fn capture(&self) -> Snapshot {
+        let bodies = self.app.resources.get::<RigidBodySet>().unwrap();
+        let body_state = |handle: RigidBodyHandle| {
+            let body = bodies.get(handle).unwrap();
+            BodyState {
+                position: body.position,
+                linvel: body.linvel,
+                angvel: body.angvel,
+            }
+        };
+
+        let mut snapshot_bodies = vec![];
+        let mut ball_velocity = Vec3::zero();
+        if let Some((ball_entity, handle)) = self.ball_body() {
+            snapshot_bodies.push((ball_entity, body_state(handle)));
+            ball_velocity = self.app.world.get::<Ball>(ball_entity).unwrap().velocity;
+        }
+        for (entity, _paddle, handle) in self.app.world.query::<(Entity, &Paddle, &RigidBodyHandleComponent)>().iter() {
+            snapshot_bodies.push((entity, body_state(handle.handle())));
+        }
+
+        let mut blocks = vec![];
+        for (entity, block, model, handle) in self.app.world.query::<(Entity, &Block, &Model, &RigidBodyHandleComponent)>().iter() {
+            if bodies.get(handle.handle()).is_some() {
+                blocks.push(BlockState {
+                    entity,
+                    body: body_state(handle.handle()),
+                    color: model.color,
+                    kind: block.kind,
+                    hits_left: block.hits_left,
+                    motion: self.app.world.get::<MovingBlock>(entity).ok().map(|moving| (*moving).clone()),
+                });
+            }
+        }
+
+        Snapshot {
+            bodies: snapshot_bodies,
+            blocks,
+            ball_velocity,
+            scoreboard: self.app.resources.get::<Scoreboard>().unwrap().clone(),
+            lives: self.app.resources.get::<Lives>().unwrap().count,
+            state: self.app.resources.get::<CurrentState>().unwrap().state,
+            combo: self.app.resources.get::<Combo>().unwrap().clone(),
+        }
+    }
+
+    fn restore(&mut self, snapshot: &Snapshot) {
+        let resources = &self.app.resources;
+        let world = &mut self.app.world;
+
+        {
+            let mut pipeline = resources.get_mut::<PhysicsPipeline>().unwrap();
+            let mut broad_phase = resources.get_mut::<BroadPhase>().unwrap();
+            let mut narrow_phase = resources.get_mut::<NarrowPhase>().unwrap();
+            let mut bodies = resources.get_mut::<RigidBodySet>().unwrap();
+            let mut colliders = resources.get_mut::<ColliderSet>().unwrap();
+            let mut joints = resources.get_mut::<JointSet>().unwrap();
+            let mut handle_to_entity = resources.get_mut::<BodyHandleToEntity>().unwrap();
+
+            // Blocks from after the snapshot go, including the ones brought back
+            // by an earlier rollback that have since been broken again
+            let mut stale = vec![];
+            for (entity, _block, handle) in world.query::<(Entity, &Block, &RigidBodyHandleComponent)>().iter() {
+                if !snapshot.blocks.iter().any(|block| block.entity == entity) {
+                    stale.push((entity, handle.handle()));
+                }
+            }
+            for (entity, handle) in stale {
+                if bodies.get(handle).is_some() {
+                    pipeline.remove_rigid_body(
+                        handle,
+                        &mut broad_phase,
+                        &mut narrow_phase,
+                        &mut bodies,
+                        &mut colliders,
+                        &mut joints,
+                    );
+                }
+                handle_to_entity.0.remove(&handle);
+                let _ = world.despawn(entity);
+            }
+
+            let mut rebuilt = vec![];
+            for (entity, state) in snapshot.bodies.iter() {
+                let physics = if world.get::<Ball>(*entity).is_ok() {
+                    crate::ball_physics()
+                } else if let Ok(paddle) = world.get::<Paddle>(*entity) {
+                    crate::paddle_physics(&paddle)
+                } else {
+                    continue;
+                };
+                rebuilt.push((*entity, physics, state));
+            }
+
+            // Blocks broken since come back as the same entities; one that's
+            // still flashing loses the flash
+            for block in snapshot.blocks.iter() {
+                let translation = block.body.position.translation;
+                let (model, body, collider, mut standing) = level::block_bundle(&BlockSpec {
+                    x: translation.x,
+                    z: translation.z,
+                    color: block.color,
+                    kind: block.kind,
+                    motion: block.motion.as_ref().map(|moving| moving.motion().clone()),
+                });
+                standing.hits_left = block.hits_left;
+
+                let standing_body = world
+                    .get::<RigidBodyHandleComponent>(block.entity)
+                    .map_or(false, |handle| bodies.get(handle.handle()).is_some());
+                if standing_body && world.get::<Block>(block.entity).is_ok() {
+                    let _ = world.insert_one(block.entity, standing);
+                    if let (Some(state), Ok(mut moving)) = (&block.motion, world.get_mut::<MovingBlock>(block.entity)) {
+                        *moving = state.clone();
+                    }
+                } else {
+                    let _ = world.despawn(block.entity);
+                    world.spawn_as_entity(block.entity, (model, standing));
+                    if let Some(moving) = &block.motion {
+                        let _ = world.insert_one(block.entity, moving.clone());
+                    }
+                }
+                rebuilt.push((block.entity, (body, collider), &block.body));
+            }
+
+            // Rapier's contact pairs can't be copied into a snapshot, and left
+            // alone they'd carry the abandoned timeline's contacts into the
+            // resimulation. Every body that moves or can be hit leaves the
+            // physics world instead and comes back fresh, before the next
+            // capture can miss it, and the first step finds its contacts again.
+            for (entity, (body, collider), state) in rebuilt {
+                if let Ok(handle) = world.get::<RigidBodyHandleComponent>(entity).map(|handle| handle.handle()) {
+                    if bodies.get(handle).is_some() {
+                        pipeline.remove_rigid_body(
+                            handle,
+                            &mut broad_phase,
+                            &mut narrow_phase,
+                            &mut bodies,
+                            &mut colliders,
+                            &mut joints,
+                        );
+                    }
+                    handle_to_entity.0.remove(&handle);
+                }
+
+                let handle = bodies.insert(body.build());
+                let collider = colliders.insert(collider.build(), handle, &mut bodies);
+                if let Some(mut body) = bodies.get_mut(handle) {
+                    body.position = state.position;
+                    body.linvel = state.linvel;
+                    body.angvel = state.angvel;
+                }
+                let _ = world.insert(
+                    entity,
+                    (RigidBodyHandleComponent::from(handle), ColliderHandleComponent::from(collider)),
+                );
+                handle_to_entity.0.insert(handle, entity);
+            }
+        }
+
+        if let Some((ball_entity, _handle)) = self.ball_body() {
+            if let Ok(mut ball) = self.app.world.get_mut::<Ball>(ball_entity) {
+                ball.velocity = snapshot.ball_velocity;
+            }
+        }
+
+        let resources = &mut self.app.resources;
+        *resources.get_mut::<Scoreboard>().unwrap() = snapshot.scoreboard.clone();
+        resources.get_mut::<Lives>().unwrap().count = snapshot.lives;
+        *resources.get_mut::<Combo>().unwrap() = snapshot.combo.clone();
+        {
+            let mut current_state = resources.get_mut::<CurrentState>().unwrap();
+            current_state.state = snapshot.state;
+            current_state.transitions.clear();
+        }
+
+        // Nothing from the abandoned timeline may leak into the resimulation
+        resources.get_mut::<Events<ResetLevel>>().unwrap().update();
+        resources.get_mut::<Events<ResetLevel>>().unwrap().update();
+        resources.get_mut::<Events<BallLost>>().unwrap().update();
+        resources.get_mut::<Events<BallLost>>().unwrap().update();
+        resources.get_mut::<Events<Contacts>>().unwrap().update();
+        resources.get_mut::<Events<Contacts>>().unwrap().update();
+        resources.get_mut::<Events<BlockDestroyed>>().unwrap().update();
+        resources.get_mut::<Events<BlockDestroyed>>().unwrap().update();
+        resources.get_mut::<Events<StateChanged>>().unwrap().update();
+        resources.get_mut::<Events<StateChanged>>().unwrap().update();
+        resources.get_mut::<Events<PointsAwarded>>().unwrap().update();
+        resources.get_mut::<Events<PointsAwarded>>().unwrap().update();
+        resources.get_mut::<Events<LevelCleared>>().unwrap().update();
+        resources.get_mut::<Events<LevelCleared>>().unwrap().update();
+        let queue = resources.get::<EventQueue>().unwrap();
+        while queue.contact_events.pop().is_ok() {}
+    }
+
+    // FNV-1a over the bodies and scores. Blocks are sorted by position since
+    // entity order isn't guaranteed to match between peers.
+    fn checksum(&self) -> u64 {
+        let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+        let mut mix = |value: u64| {
+            for byte in value.to_le_bytes().iter() {
+                hash ^= *byte as u64;
+                hash = hash.wrapping_mul(0x0100_0000_01b3);
+            }
+        };
+
+        let bodies = self.app.resources.get::<RigidBodySet>().unwrap();
+        let mix_body = |handle: RigidBodyHandle, mix: &mut dyn FnMut(u64)| {
+            if let Some(body) = bodies.get(handle) {
+                let translation = body.position.translation;
+                for value in [translation.x, translation.y, translation.z, body.linvel.x, body.linvel.z].iter() {
+                    mix(value.to_bits() as u64);
+                }
+            }
+        };
+
+        if let Some((_ball_entity, handle)) = self.ball_body() {
+            mix_body(handle, &mut mix);
+        }
+
+        let mut paddles = vec![];
+        for (paddle, handle) in self.app.world.query::<(&Paddle, &RigidBodyHandleComponent)>().iter() {
+            paddles.push((paddle.player, handle.handle()));
+        }
+        paddles.sort_by_key(|(player, _handle)| *player);
+        for (_player, handle) in paddles {
+            mix_body(handle, &mut mix);
+        }
+
+        let mut blocks = vec![];
+        for (_block, handle) in self.app.world.query::<(&Block, &RigidBodyHandleComponent)>().iter() {
+            if let Some(body) = bodies.get(handle.handle()) {
+                let translation = body.position.translation;
+                blocks.push((translation.x.to_bits(), translation.z.to_bits()));
+            }
+        }
+        blocks.sort();
+        for (x, z) in blocks {
+            mix(x as u64);
+            mix(z as u64);
+        }
+
+        let scoreboard = self.app.resources.get::<Scoreboard>().unwrap();
+        let lives = self.app.resources.get::<Lives>().unwrap();
+        for player in 0..MAX_PLAYERS {
+            mix(scoreboard.players[player] as u64);
+            mix(lives.count[player] as u64);
+        }
+        mix(self.app.resources.get::<CurrentState>().unwrap().state as u64);
+        hash
+    }
+
+    // Only with --netplay-bot, which the loopback check and the tests use:
+    // steers the paddle towards the ball and serves whenever it can
+    fn bot_input(&self) -> NetInput {
+        let bodies = self.app.resources.get::<RigidBodySet>().unwrap();
+        let ball_x = self
+            .ball_body()
+            .and_then(|(_entity, handle)| bodies.get(handle))
+            .map(|body| body.position.translation.x)
+            .unwrap_or(0.0);
+
+        let mut paddle_x = ball_x;
+        for (paddle, handle) in self.app.world.query::<(&Paddle, &RigidBodyHandleComponent)>().iter() {
+            if paddle.player == self.local_player {
+                if let Some(body) = bodies.get(handle.handle()) {
+                    paddle_x = body.position.translation.x;
+                }
+            }
+        }
+
+        let offset = ball_x - paddle_x;
+        NetInput {
+            direction: if offset.abs() < 1.0 { 0 } else { offset.signum() as i8 },
+            serve: self.app.resources.get::<CurrentState>().unwrap().state == GameState::ArenaStart,
+        }
+    }
+
+    fn view(&self) -> View {
+        let bodies = self.app.resources.get::<RigidBodySet>().unwrap();
+        let position_of = |handle: &RigidBodyHandleComponent| {
+            bodies.get(handle.handle()).map(|body| {
+                let translation = body.position.translation;
+                Vec3::new(translation.x, translation.y, translation.z)
+            })
+        };
+
+        let ball = self.ball_body().and_then(|(_entity, handle)| bodies.get(handle)).map(|body| {
+            let translation = body.position.translation;
+            Vec3::new(translation.x, translation.y, translation.z)
+        });
+        let mut paddles = vec![];
+        for (paddle, handle) in self.app.world.query::<(&Paddle, &RigidBodyHandleComponent)>().iter() {
+            if let Some(position) = position_of(handle) {
+                paddles.push((paddle.player, position));
+            }
+        }
+        let mut blocks = vec![];
+        for (entity, _block, model, handle) in self.app.world.query::<(Entity, &Block, &Model, &RigidBodyHandleComponent)>().iter() {
+            if let Some(position) = position_of(handle) {
+                blocks.push((entity, position, model.color));
+            }
+        }
+
+        View {
+            state: self.app.resources.get::<CurrentState>().unwrap().state,
+            scoreboard: self.app.resources.get::<Scoreboard>().unwrap().clone(),
+            lives: self.app.resources.get::<Lives>().unwrap().count,
+            ball,
+            paddles,
+            blocks,
+        }
+    }
+}
+
+// What the window shows of the simulation's latest frame
+struct View {
+    state: GameState,
+    scoreboard: Scoreboard,
+    lives: [usize; MAX_PLAYERS],
+    ball: Option<Vec3>,
+    paddles: Vec<(usize, Vec3)>,
+    blocks: Vec<(Entity, Vec3, Color)>,
+}
+
+// Shared between the window and the simulation thread
+#[derive(Default)]
+struct PeerLink {
+    input: Mutex<NetInput>,
+    view: Mutex<Option<View>>,
+    status: Mutex<String>,
+}
+
+// One peer of a two player versus match, paced at 60 frames per second. The
+// player steers with the arrow keys and serves with SPACE in a window of its
+// own, while the simulation runs on a thread of its own so rendering never
+// holds up a frame. With `bot` the peer plays itself and stays headless.
+pub fn run_peer(local_player: usize, local: String, peer: String, frames: u32, conditions: LinkConditions, bot: bool) {
+    let transport = match UdpTransport::bind(&local, &peer) {
+        Ok(transport) => transport,
+        Err(e) => {
+            eprintln!("Unable to open {} for netplay: {}", local, e);
+            process::exit(1);
+        },
+    };
+    let transport = RefCell::new(SimulatedLink {
+        inner: transport,
+        conditions,
+        rng: Rng::new(local_player as u64 + 1),
+        in_flight: vec![],
+    });
+
+    if bot {
+        env::headless_app(GameMode::Versus, START_LIVES)
+            .set_runner(move |app| {
+                let mut session = Session::new(app, local_player);
+                let result = play(&mut session, &mut *transport.borrow_mut(), frames, None);
+                report(&session);
+                if let Err(message) = result {
+                    eprintln!("Player {}: {}", local_player + 1, message);
+                    process::exit(1);
+                }
+            })
+            .run();
+        return;
+    }
+
+    let link = Arc::new(PeerLink::default());
+    let simulation_link = link.clone();
+    thread::spawn(move || {
+        env::headless_app(GameMode::Versus, START_LIVES)
+            .set_runner(move |app| {
+                let mut session = Session::new(app, local_player);
+                let result = play(&mut session, &mut *transport.borrow_mut(), frames, Some(&simulation_link));
+                report(&session);
+                *simulation_link.status.lock().unwrap() = match result {
+                    Ok(()) => "Match over".to_string(),
+                    Err(message) => {
+                        eprintln!("Player {}: {}", local_player + 1, message);
+                        format!("Match stopped: {}", message)
+                    },
+                };
+            })
+            .run();
+    });
+    run_window(local_player, link);
+}
+
+struct NetplayWindow {
+    local_player: usize,
+    link: Arc<PeerLink>,
+    ball: Option<Entity>,
+    paddles: [Option<Entity>; MAX_PLAYERS],
+    // Simulated block entities to the models drawn for them
+    blocks: HashMap<Entity, Entity>,
+}
+
+fn run_window(local_player: usize, link: Arc<PeerLink>) {
+    App::build()
+        .add_resource(Msaa { samples: 4 })
+        .add_resource(WindowDescriptor {
+            title: format!("Breakout - Netplay Player {}", local_player + 1),
+            width: 1920,
+            height: 1080,
+            vsync: true,
+            resizable: true,
+            ..Default::default()
+        })
+        .add_resource(NetplayWindow {
+            local_player,
+            link,
+            ball: None,
+            paddles: [None; MAX_PLAYERS],
+            blocks: HashMap::new(),
+        })
+        .add_resource(Scoreboard::default())
+        .add_resource(Lives { count: [START_LIVES; MAX_PLAYERS] })
+        .add_resource(RunTimer { seconds: 0.0 })
+        .add_resource(ActivePowerUps::default())
+        .add_resource(Combo::default())
+        .add_resource(CurrentState::new(GameState::ArenaStart))
+        .add_resource(Levels::builtin())
+        .add_resource(GameMode::Versus)
+        .add_resource(HighScores::load("highscores.txt"))
+        .add_resource(TimeAttack::load())
+        .add_resource(SurvivalHighScores(HighScores::load(survival::SURVIVAL_HIGH_SCORES_PATH)))
+        .add_startup_system(crate::setup.system())
+        .add_startup_system(hud::setup_hud.system())
+        .add_startup_system(spectate::setup_spectator_arena.system())
+        .add_system(netplay_input_system.system())
+        .add_system(netplay_view_system.system())
+        .add_system(hud::hud_system.system())
+        .add_system(window::ui_scale_system.system())
+        .add_default_plugins()
+        .run();
+}
+
+fn netplay_input_system(keyboard_input: Res<Input<KeyCode>>, window: Res<NetplayWindow>) {
+    let mut direction = 0;
+    if keyboard_input.pressed(KeyCode::Left) {
+        direction += 1;
+    }
+    if keyboard_input.pressed(KeyCode::Right) {
+        direction -= 1;
+    }
+    *window.link.input.lock().unwrap() = NetInput {
+        direction,
+        serve: keyboard_input.pressed(KeyCode::Space),
+    };
+}
+
+fn netplay_view_system(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut window: ResMut<NetplayWindow>,
+    mut current_state: ResMut<CurrentState>,
+    mut scoreboard: ResMut<Scoreboard>,
+    mut lives: ResMut<Lives>,
+    mut translations: Query<Mut<Translation>>,
+    mut infoboard: Query<(Mut<Text>, &Infoboard)>,
+) {
+    let window = &mut *window;
+    let status = window.link.status.lock().unwrap().clone();
+    for (mut text, _infoboard) in &mut infoboard.iter() {
+        text.value = if status.is_empty() {
+            format!("NETPLAY - Player {}", window.local_player + 1)
+        } else {
+            format!("NETPLAY - Player {} - {}", window.local_player + 1, status)
+        };
+    }
+
+    let view = match window.link.view.lock().unwrap().take() {
+        Some(view) => view,
+        None => return,
+    };
+    current_state.state = view.state;
+    *scoreboard = view.scoreboard;
+    lives.count = view.lives;
+
+    let mut place = |entity: &mut Option<Entity>, mesh: &'static str, color: Color, position: Vec3| match *entity {
+        Some(entity) => {
+            if let Ok(mut translation) = translations.get_mut::<Translation>(entity) {
+                translation.0 = position;
+            }
+        },
+        None => {
+            *entity = Some(spectate::spawn_model(
+                &mut commands,
+                &asset_server,
+                &mut materials,
+                mesh,
+                color,
+                position,
+                Quat::identity(),
+            ));
+        },
+    };
+
+    if let Some(position) = view.ball {
+        place(&mut window.ball, "assets/blender/ball/export/ball.gltf", Color::rgb(0.7, 0.0, 0.0), position);
+    }
+    for (player, position) in view.paddles {
+        if player < MAX_PLAYERS {
+            place(&mut window.paddles[player], "assets/blender/paddle/export/paddle.gltf", Color::rgb(0.9, 0.92, 1.0), position);
+        }
+    }
+
+    // A rollback can bring a broken block back, so ones missing from this
+    // frame are only moved out of sight
+    let standing: HashMap<Entity, (Vec3, Color)> = view
+        .blocks
+        .into_iter()
+        .map(|(block, position, color)| (block, (position, color)))
+        .collect();
+    for (block, model) in window.blocks.iter() {
+        if !standing.contains_key(block) {
+            place(&mut Some(*model), "assets/blender/block/export/block.gltf", Color::rgb(0.0, 0.0, 0.0), Vec3::new(0.0, -100.0, 0.0));
+        }
+    }
+    for (block, (position, color)) in standing {
+        let mut model = window.blocks.get(&block).copied();
+        place(&mut model, "assets/blender/block/export/block.gltf", color, position);
+        window.blocks.insert(block, model.unwrap());
+    }
+}
+
+fn report(session: &Session) {
+    let player = session.local_player + 1;
+    let stats = &session.stats;
+    let lives = session.app.resources.get::<Lives>().unwrap().count;
+    let scoreboard = session.app.resources.get::<Scoreboard>().unwrap();
+    println!(
+        "Player {}: {} frames, {} rollbacks ({} frames resimulated, deepest {}), \
+         {} stalled ticks, {} checksums verified",
+        player,
+        session.frame,
+        stats.rollbacks,
+        stats.resimulated_frames,
+        stats.max_rollback_frames,
+        stats.stalled_ticks,
+        stats.checksums_verified,
+    );
+    println!(
+        "Player {}: P1 {} ({} lives), P2 {} ({} lives)",
+        player,
+        scoreboard.players[0],
+        lives[0],
+        scoreboard.players[1],
+        lives[1],
+    );
+}
+
+// Without a link the bot plays for this peer
+fn play(session: &mut Session, transport: &mut dyn Transport, frames: u32, link: Option<&PeerLink>) -> Result<(), String> {
+    let tick = Duration::from_secs_f32(STEP_SECONDS);
+    let mut next_tick = Instant::now();
+    let mut last_heard = Instant::now();
+    let mut heard = false;
+    let mut linger = 0;
+
+    loop {
+        while let Some(bytes) = transport.recv() {
+            if let Some(packet) = Packet::decode(&bytes) {
+                session.receive(packet);
+                last_heard = Instant::now();
+                heard = true;
+            }
+        }
+
+        session.rollback();
+        if let Err(desync) = session.verify() {
+            return Err(format!(
+                "desync at frame {}: local {:016x}, remote {:016x}",
+                desync.frame, desync.local, desync.remote,
+            ));
+        }
+        session.prune();
+
+        if session.frame < frames {
+            if session.can_advance() {
+                let input = match link {
+                    Some(link) => *link.input.lock().unwrap(),
+                    None => session.bot_input(),
+                };
+                session.advance(input);
+            } else {
+                session.stats.stalled_ticks += 1;
+            }
+        }
+        transport.send(&session.packet().encode());
+        if let Some(link) = link {
+            let waiting = if heard { String::new() } else { "Waiting for the other player".to_string() };
+            *link.status.lock().unwrap() = waiting;
+            *link.view.lock().unwrap() = Some(session.view());
+        }
+
+        let timeout = if heard { PEER_TIMEOUT } else { CONNECT_TIMEOUT };
+        let finished = session.frame >= frames
+            && session.remote_inputs.len() as u32 >= frames
+            && session.peer_ack >= frames;
+        if finished {
+            linger += 1;
+            if linger >= LINGER_FRAMES {
+                return Ok(());
+            }
+        } else if last_heard.elapsed() > timeout {
+            return Err("lost contact with the peer".to_string());
+        }
+
+        next_tick += tick;
+        let now = Instant::now();
+        if next_tick > now {
+            thread::sleep(next_tick - now);
+        } else {
+            next_tick = now;
+        }
+    }
+}
+
+// Runs both peers as separate processes talking over loopback
+pub fn run_loopback(frames: u32, conditions: LinkConditions) {
+    let exe = match current_exe() {
+        Ok(exe) => exe,
+        Err(e) => {
+            eprintln!("Unable to find the game executable: {}", e);
+            return;
+        },
+    };
+
+    let address = |port: u16| format!("127.0.0.1:{}", port);
+    let mut children = vec![];
+    for player in 0..2 {
+        let child = Command::new(&exe)
+            .arg("--netplay")
+            .arg(player.to_string())
+            .arg(address(LOOPBACK_PORTS[player]))
+            .arg(address(LOOPBACK_PORTS[1 - player]))
+            .arg(frames.to_string())
+            .arg("--netplay-bot")
+            .arg("--net-latency")
+            .arg(conditions.latency_ms.to_string())
+            .arg("--net-loss")
+            .arg(conditions.loss_percent.to_string())
+            .spawn();
+        match child {
+            Ok(child) => children.push(child),
+            Err(e) => eprintln!("Unable to start player {}: {}", player + 1, e),
+        }
+    }
+
+    let mut all_ok = children.len() == 2;
+    for mut child in children {
+        all_ok &= child.wait().map(|status| status.success()).unwrap_or(false);
+    }
+    if all_ok {
+        println!("Both peers finished in sync");
+    } else {
+        eprintln!("Loopback netplay failed");
+        process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use super::*;
+
+    const FRAMES: u32 = 900;
+    const ROLLBACK_FRAME: u32 = 120;
+
+    // The versus app with startup done, as run_peer's runner gets it
+    fn started_app() -> App {
+        let slot = Rc::new(RefCell::new(None));
+        let runner_slot = slot.clone();
+        env::headless_app(GameMode::Versus, START_LIVES)
+            .set_runner(move |app| *runner_slot.borrow_mut() = Some(app))
+            .run();
+        let app = slot.borrow_mut().take();
+        app.unwrap()
+    }
+
+    #[test]
+    fn rolling_back_twice_replays_the_same_frames() {
+        let mut session = Session::new(started_app(), 0);
+        // The peer sweeps its paddle back and forth and serves whenever it can
+        session.remote_inputs = (0..FRAMES)
+            .map(|frame| NetInput {
+                direction: [1, 0, -1, 0][(frame / 30) as usize % 4],
+                serve: true,
+            })
+            .collect();
+        while session.frame < FRAMES {
+            let input = session.bot_input();
+            session.advance(input);
+        }
+
+        // Blocks broken after the rollback frame have to come back for it
+        let standing = session.capture().blocks.len();
+        assert!(session.snapshots[&ROLLBACK_FRAME].blocks.len() > standing);
+
+        let original = session.checksums.clone();
+        for attempt in 1..=2 {
+            session.rollback_to = Some(ROLLBACK_FRAME);
+            session.rollback();
+            assert_eq!(session.frame, FRAMES);
+            let diverged = (ROLLBACK_FRAME..FRAMES).find(|frame| session.checksums[frame] != original[frame]);
+            assert_eq!(diverged, None, "rollback {} diverged", attempt);
+        }
+    }
+
+    // Both peers in one process, with player 2's packets held back longer so
+    // each side mispredicts the other and rolls back now and then
+    #[test]
+    fn loopback_peers_stay_in_sync() {
+        let mut sessions = [Session::new(started_app(), 0), Session::new(started_app(), 1)];
+        let delays = [2, 7];
+        // Due tick, receiving player and packet
+        let mut in_flight: Vec<(usize, usize, Vec<u8>)> = vec![];
+
+        for tick in 0..(FRAMES as usize + LINGER_FRAMES) {
+            for player in 0..2 {
+                let session = &mut sessions[player];
+                let (arrived, waiting): (Vec<_>, Vec<_>) = in_flight
+                    .into_iter()
+                    .partition(|(due, to, _bytes)| *due <= tick && *to == player);
+                in_flight = waiting;
+                for (_due, _to, bytes) in arrived {
+                    session.receive(Packet::decode(&bytes).unwrap());
+                }
+
+                session.rollback();
+                if let Err(desync) = session.verify() {
+                    panic!("player {} desynced at frame {}", player + 1, desync.frame);
+                }
+                session.prune();
+                if session.frame < FRAMES && session.can_advance() {
+                    let input = session.bot_input();
+                    session.advance(input);
+                }
+                in_flight.push((tick + delays[player], 1 - player, session.packet().encode()));
+            }
+        }
+
+        for session in sessions.iter() {
+            assert_eq!(session.frame, FRAMES);
+            assert!(session.stats.rollbacks > 0);
+            assert!(session.stats.checksums_verified > 0);
+        }
+        for (frame, checksum) in sessions[0].checksums.iter() {
+            if let Some(other) = sessions[1].checksums.get(frame) {
+                assert_eq!(checksum, other, "frame {}", frame);
+            }
+        }
+    }
+}
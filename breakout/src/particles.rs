@@ -0,0 +1,234 @@
+use std::collections::HashMap;
+use bevy::prelude::*;
+use bevy_rapier3d::physics::RigidBodyHandleComponent;
+use bevy_rapier3d::rapier::dynamics::RigidBodySet;
+use crate::{Ball, BlockDestroyed, Contacts};
+use crate::boss::BossEvent;
+use crate::drones::DroneDestroyed;
+use crate::rng::Rng;
+use crate::settings::Settings;
+
+// Hard cap on live particles so multiball chaos can't tank the frame rate
+const MAX_PARTICLES: usize = 400;
+const PARTICLE_GRAVITY: f32 = 40.0;
+
+const DEBRIS_COUNT: usize = 12;
+const DEBRIS_SPEED: f32 = 18.0;
+const DEBRIS_LIFETIME: f32 = 0.7;
+
+const SPARK_COUNT: usize = 6;
+const SPARK_SPEED: f32 = 25.0;
+const SPARK_LIFETIME: f32 = 0.25;
+
+const TRAIL_INTERVAL: f32 = 0.02;
+const TRAIL_LIFETIME: f32 = 0.3;
+
+pub struct Particle {
+    velocity: Vec3,
+    gravity: f32,
+    size: f32,
+    age: f32,
+    lifetime: f32,
+}
+
+pub struct Particles {
+    live: usize,
+    rng: Rng,
+    mesh: Option<Handle<Mesh>>,
+    spark_material: Option<Handle<StandardMaterial>>,
+    trail_material: Option<Handle<StandardMaterial>>,
+    // One material per debris color, keyed by the color's bits since Color
+    // can't be hashed
+    debris_materials: HashMap<[u32; 4], Handle<StandardMaterial>>,
+    trail_seconds: f32,
+}
+
+impl Default for Particles {
+    fn default() -> Self {
+        Particles {
+            live: 0,
+            rng: Rng::new(0),
+            mesh: None,
+            spark_material: None,
+            trail_material: None,
+            debris_materials: HashMap::new(),
+            trail_seconds: 0.0,
+        }
+    }
+}
+
+impl Particles {
+    fn debris_material(&mut self, materials: &mut Assets<StandardMaterial>, color: Color) -> Handle<StandardMaterial> {
+        let key = [color.r.to_bits(), color.g.to_bits(), color.b.to_bits(), color.a.to_bits()];
+        *self
+            .debris_materials
+            .entry(key)
+            .or_insert_with(|| materials.add(color.into()))
+    }
+
+    fn emit(
+        &mut self,
+        commands: &mut Commands,
+        material: Handle<StandardMaterial>,
+        position: Vec3,
+        velocity: Vec3,
+        gravity: f32,
+        size: f32,
+        lifetime: f32,
+    ) {
+        if self.live >= MAX_PARTICLES {
+            return;
+        }
+        self.live += 1;
+
+        commands
+            .spawn(PbrComponents {
+                mesh: self.mesh.unwrap(),
+                material,
+                translation: Translation(position),
+                scale: Scale(size),
+                ..Default::default()
+            })
+            .with(Particle {
+                velocity,
+                gravity,
+                size,
+                age: 0.0,
+                lifetime,
+            });
+    }
+
+    fn burst(
+        &mut self,
+        commands: &mut Commands,
+        material: Handle<StandardMaterial>,
+        position: Vec3,
+        count: usize,
+        speed: f32,
+        lifetime: f32,
+    ) {
+        for _ in 0..count {
+            let velocity = Vec3::new(
+                self.rng.range(-1.0, 1.0),
+                self.rng.range(0.2, 1.0),
+                self.rng.range(-1.0, 1.0),
+            ) * speed;
+            let size = self.rng.range(0.6, 1.0);
+            self.emit(commands, material, position, velocity, PARTICLE_GRAVITY, size, lifetime);
+        }
+    }
+}
+
+pub fn setup_particles(
+    mut particles: ResMut<Particles>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    particles.mesh = Some(meshes.add(Mesh::from(shape::Cube { size: 0.4 })));
+    particles.spark_material = Some(materials.add(Color::rgb(3.0, 2.5, 1.2).into()));
+    particles.trail_material = Some(materials.add(Color::rgb(0.9, 0.1, 0.1).into()));
+}
+
+pub fn particle_event_system(
+    mut commands: Commands,
+    bodies: Res<RigidBodySet>,
+    mut particles: ResMut<Particles>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut contact_reader: Local<EventReader<Contacts>>,
+    contact_events: Res<Events<Contacts>>,
+    mut destroyed_reader: Local<EventReader<BlockDestroyed>>,
+    destroyed_events: Res<Events<BlockDestroyed>>,
+    mut drone_reader: Local<EventReader<DroneDestroyed>>,
+    drone_events: Res<Events<DroneDestroyed>>,
+    mut boss_reader: Local<EventReader<BossEvent>>,
+    boss_events: Res<Events<BossEvent>>,
+    handles: Query<&RigidBodyHandleComponent>,
+) {
+    for block in destroyed_reader.iter(&destroyed_events) {
+        let material = particles.debris_material(&mut materials, block.color);
+        particles.burst(&mut commands, material, block.position, DEBRIS_COUNT, DEBRIS_SPEED, DEBRIS_LIFETIME);
+    }
+
+    for drone in drone_reader.iter(&drone_events) {
+        let material = particles.debris_material(&mut materials, drone.color);
+        particles.burst(&mut commands, material, drone.position, DEBRIS_COUNT, DEBRIS_SPEED, DEBRIS_LIFETIME);
+    }
+
+    for event in boss_reader.iter(&boss_events) {
+        match *event {
+            BossEvent::PartDestroyed(position, color) => {
+                let material = particles.debris_material(&mut materials, color);
+                particles.burst(&mut commands, material, position, DEBRIS_COUNT, DEBRIS_SPEED, DEBRIS_LIFETIME);
+            },
+            BossEvent::WeakPointHit(position) | BossEvent::PaddleHit(position) => {
+                let material = particles.spark_material.unwrap();
+                particles.burst(&mut commands, material, position, SPARK_COUNT, SPARK_SPEED, SPARK_LIFETIME);
+            },
+            BossEvent::Deflected(_) => (),
+        }
+    }
+
+    for contact in contact_reader.iter(&contact_events) {
+        let ball = match contact {
+            Contacts::BallPaddle(ball, _) => ball,
+            Contacts::BallSideWall(ball, _) => ball,
+            Contacts::BallTopWall(ball, _) => ball,
+            Contacts::BallBlock(_, _) => continue,
+        };
+
+        if let Ok(handle) = handles.get::<RigidBodyHandleComponent>(*ball) {
+            let translation = bodies.get(handle.handle()).unwrap().position.translation;
+            let position = Vec3::new(translation.x, translation.y, translation.z);
+            let material = particles.spark_material.unwrap();
+            particles.burst(&mut commands, material, position, SPARK_COUNT, SPARK_SPEED, SPARK_LIFETIME);
+        }
+    }
+}
+
+pub fn ball_trail_system(
+    mut commands: Commands,
+    time: Res<Time>,
+    settings: Res<Settings>,
+    bodies: Res<RigidBodySet>,
+    mut particles: ResMut<Particles>,
+    mut balls: Query<(&Ball, &RigidBodyHandleComponent)>,
+) {
+    if !settings.ball_trail {
+        return;
+    }
+
+    particles.trail_seconds += time.delta_seconds;
+    if particles.trail_seconds < TRAIL_INTERVAL {
+        return;
+    }
+    particles.trail_seconds = 0.0;
+
+    for (_ball, handle) in &mut balls.iter() {
+        let translation = bodies.get(handle.handle()).unwrap().position.translation;
+        let position = Vec3::new(translation.x, translation.y, translation.z);
+        let material = particles.trail_material.unwrap();
+        particles.emit(&mut commands, material, position, Vec3::zero(), 0.0, 1.8, TRAIL_LIFETIME);
+    }
+}
+
+pub fn particle_system(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut particles: ResMut<Particles>,
+    mut query: Query<(Entity, Mut<Particle>, Mut<Translation>, Mut<Scale>)>,
+) {
+    for (entity, mut particle, mut translation, mut scale) in &mut query.iter() {
+        particle.age += time.delta_seconds;
+        if particle.age >= particle.lifetime {
+            particles.live -= 1;
+            commands.despawn(entity);
+            continue;
+        }
+
+        particle.velocity -= Vec3::new(0.0, particle.gravity * time.delta_seconds, 0.0);
+        translation.0 += particle.velocity * time.delta_seconds;
+
+        // Shrink out instead of fading so no transparent materials are needed
+        scale.0 = particle.size * (1.0 - particle.age / particle.lifetime);
+    }
+}
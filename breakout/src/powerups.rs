@@ -0,0 +1,44 @@
+use bevy::prelude::*;
+use crate::{CurrentState, GameState};
+
+pub struct ActivePowerUp {
+    pub name: &'static str,
+    pub seconds_left: f32,
+}
+
+// Timed power-ups currently affecting the player, shown on the HUD
+#[derive(Default)]
+pub struct ActivePowerUps {
+    pub active: Vec<ActivePowerUp>,
+}
+
+impl ActivePowerUps {
+    // Collecting a power-up that's already running restarts its timer
+    pub fn activate(&mut self, name: &'static str, seconds: f32) {
+        match self.active.iter_mut().find(|power_up| power_up.name == name) {
+            Some(power_up) => power_up.seconds_left = seconds,
+            None => self.active.push(ActivePowerUp { name, seconds_left: seconds }),
+        }
+    }
+
+    pub fn is_active(&self, name: &str) -> bool {
+        self.active.iter().any(|power_up| power_up.name == name)
+    }
+}
+
+pub fn power_up_timer_system(
+    time: Res<Time>,
+    current_state: Res<CurrentState>,
+    mut power_ups: ResMut<ActivePowerUps>,
+) {
+    match current_state.state {
+        GameState::Playing => {
+            for power_up in power_ups.active.iter_mut() {
+                power_up.seconds_left -= time.delta_seconds;
+            }
+            power_ups.active.retain(|power_up| power_up.seconds_left > 0.0);
+        },
+        GameState::GameOver => power_ups.active.clear(),
+        _ => (),
+    };
+}
@@ -0,0 +1,295 @@
+use std::env::current_exe;
+use std::io::{self, BufRead, BufReader, Write};
+use std::process::{Command, Stdio};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use crate::env::{self, Action, BreakoutEnv, Observation};
+
+// One JSON object per line in each direction. Requests look like
+//   {"cmd": "reset", "seed": 7}
+//   {"cmd": "step", "action": "left"}
+//   {"cmd": "observe"}
+// and every request is answered with a single snapshot or error line.
+#[derive(Deserialize)]
+#[serde(tag = "cmd", rename_all = "snake_case")]
+enum Request {
+    Reset {
+        #[serde(default)]
+        seed: u64,
+    },
+    Step {
+        action: Action,
+    },
+    Observe,
+    Quit,
+}
+
+#[derive(Serialize)]
+struct Snapshot<'a> {
+    tick: usize,
+    observation: &'a Observation,
+    reward: f32,
+    done: bool,
+}
+
+#[derive(Serialize)]
+struct ErrorReply {
+    error: String,
+}
+
+struct Session {
+    tick: usize,
+    // Whether the last step ended the episode, until the next reset
+    done: bool,
+}
+
+impl Session {
+    // Returns false once the client asked to quit
+    fn handle(&mut self, env: &mut BreakoutEnv, line: &str, writer: &mut dyn Write) -> io::Result<bool> {
+        let request = match serde_json::from_str::<Request>(line) {
+            Ok(request) => request,
+            Err(e) => {
+                let reply = ErrorReply { error: e.to_string() };
+                writeln!(writer, "{}", serde_json::to_string(&reply).unwrap())?;
+                return writer.flush().map(|_| true);
+            },
+        };
+
+        let (observation, reward, done) = match request {
+            Request::Reset { seed } => {
+                self.tick = 0;
+                self.done = false;
+                (env.reset(seed), 0.0, false)
+            },
+            Request::Step { action } => {
+                self.tick += 1;
+                let (observation, reward, done) = env.step(action);
+                self.done = done;
+                (observation, reward, done)
+            },
+            Request::Observe => (env.observe(), 0.0, self.done),
+            Request::Quit => return Ok(false),
+        };
+
+        let snapshot = Snapshot {
+            tick: self.tick,
+            observation: &observation,
+            reward,
+            done,
+        };
+        writeln!(writer, "{}", serde_json::to_string(&snapshot).unwrap())?;
+        writer.flush()?;
+        Ok(true)
+    }
+}
+
+fn serve_connection(env: &mut BreakoutEnv, reader: &mut dyn BufRead, writer: &mut dyn Write) -> io::Result<()> {
+    let mut session = Session { tick: 0, done: false };
+    for line in reader.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        if !session.handle(env, &line, writer)? {
+            break;
+        }
+    }
+    Ok(())
+}
+
+// `target` is either "stdio" or a Unix socket path to listen on
+pub fn run_server(target: String) {
+    env::run(move |env| {
+        let result = if target == "stdio" {
+            let stdin = io::stdin();
+            let stdout = io::stdout();
+            serve_connection(env, &mut stdin.lock(), &mut stdout.lock())
+        } else {
+            serve_socket(env, &target)
+        };
+
+        if let Err(e) = result {
+            eprintln!("Remote control stopped: {}", e);
+        }
+    });
+}
+
+#[cfg(unix)]
+fn serve_socket(env: &mut BreakoutEnv, path: &str) -> io::Result<()> {
+    use std::os::unix::net::UnixListener;
+
+    let _ = std::fs::remove_file(path);
+    let listener = UnixListener::bind(path)?;
+    eprintln!("Listening for controllers on {}", path);
+
+    // Clients are served one at a time; each gets its own session
+    for stream in listener.incoming() {
+        let stream = stream?;
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut writer = stream;
+        if let Err(e) = serve_connection(env, &mut reader, &mut writer) {
+            eprintln!("Controller disconnected: {}", e);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn serve_socket(_env: &mut BreakoutEnv, _path: &str) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Other, "Unix sockets are not supported on this platform"))
+}
+
+// Stand-in for an external controller: follows the ball with the paddle.
+// With "stdio" it launches this binary as a child process, otherwise it
+// connects to an already running server's socket.
+pub fn run_client(target: String, episodes: usize) {
+    let result = if target == "stdio" {
+        run_child_client(episodes)
+    } else {
+        run_socket_client(&target, episodes)
+    };
+
+    if let Err(e) = result {
+        eprintln!("Stand-in client failed: {}", e);
+    }
+}
+
+fn run_child_client(episodes: usize) -> io::Result<()> {
+    let mut child = Command::new(current_exe()?)
+        .arg("--remote")
+        .arg("stdio")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()?;
+
+    let mut writer = child.stdin.take().unwrap();
+    let mut reader = BufReader::new(child.stdout.take().unwrap());
+    play(&mut reader, &mut writer, episodes)?;
+    drop(writer);
+    child.wait()?;
+    Ok(())
+}
+
+#[cfg(unix)]
+fn run_socket_client(path: &str, episodes: usize) -> io::Result<()> {
+    use std::os::unix::net::UnixStream;
+
+    let stream = UnixStream::connect(path)?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = stream;
+    play(&mut reader, &mut writer, episodes)
+}
+
+#[cfg(not(unix))]
+fn run_socket_client(_path: &str, _episodes: usize) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Other, "Unix sockets are not supported on this platform"))
+}
+
+fn exchange(reader: &mut dyn BufRead, writer: &mut dyn Write, request: &str) -> io::Result<Value> {
+    writeln!(writer, "{}", request)?;
+    writer.flush()?;
+
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    serde_json::from_str(&line).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+fn play(reader: &mut dyn BufRead, writer: &mut dyn Write, episodes: usize) -> io::Result<()> {
+    for episode in 0..episodes {
+        let mut snapshot = exchange(reader, writer, &format!(r#"{{"cmd": "reset", "seed": {}}}"#, episode))?;
+        let mut total_reward = 0.0;
+
+        while !snapshot["done"].as_bool().unwrap_or(true) {
+            let observation = &snapshot["observation"];
+            let ball_x = observation["ball_position"][0].as_f64().unwrap_or(0.0);
+            let paddle_x = observation["paddle_x"].as_f64().unwrap_or(0.0);
+            let action = if ball_x > paddle_x + 1.0 {
+                "left"
+            } else if ball_x < paddle_x - 1.0 {
+                "right"
+            } else {
+                "stay"
+            };
+
+            snapshot = exchange(reader, writer, &format!(r#"{{"cmd": "step", "action": "{}"}}"#, action))?;
+            if let Some(error) = snapshot["error"].as_str() {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, error.to_string()));
+            }
+            total_reward += snapshot["reward"].as_f64().unwrap_or(0.0);
+        }
+
+        println!(
+            "episode {}: {} ticks, reward {}",
+            episode,
+            snapshot["tick"].as_u64().unwrap_or(0),
+            total_reward,
+        );
+    }
+
+    writeln!(writer, r#"{{"cmd": "quit"}}"#)?;
+    writer.flush()
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::os::unix::net::UnixStream;
+    use std::thread;
+    use super::*;
+
+    // Serves one end of a socket pair in-process while a controller thread
+    // drives the other, the same way a client on the real socket would
+    #[test]
+    fn reset_step_observe_round_trip() {
+        let (server, client) = UnixStream::pair().unwrap();
+
+        let controller = thread::spawn(move || -> io::Result<()> {
+            let mut reader = BufReader::new(client.try_clone()?);
+            let mut writer = client;
+
+            let reset = exchange(&mut reader, &mut writer, r#"{"cmd": "reset", "seed": 3}"#)?;
+            assert_eq!(reset["tick"], 0);
+            assert_eq!(reset["done"], false);
+            let blocks = reset["observation"]["blocks"].as_array().map(|blocks| blocks.len());
+            assert_eq!(blocks, Some(env::GRID_ROWS * env::GRID_COLUMNS));
+
+            let step = exchange(&mut reader, &mut writer, r#"{"cmd": "step", "action": "left"}"#)?;
+            assert_eq!(step["tick"], 1);
+            assert!(step["reward"].is_number());
+
+            // Observing doesn't advance the game
+            let observe = exchange(&mut reader, &mut writer, r#"{"cmd": "observe"}"#)?;
+            assert_eq!(observe["tick"], 1);
+            assert_eq!(observe["observation"], step["observation"]);
+
+            let unknown = exchange(&mut reader, &mut writer, r#"{"cmd": "jump"}"#)?;
+            assert!(unknown["error"].is_string());
+
+            // Standing still ends the episode sooner or later, by losing the
+            // ball or at the step limit, and observing after that still
+            // reports it finished
+            let mut last = step;
+            while last["done"] == false {
+                last = exchange(&mut reader, &mut writer, r#"{"cmd": "step", "action": "stay"}"#)?;
+            }
+            let finished = exchange(&mut reader, &mut writer, r#"{"cmd": "observe"}"#)?;
+            assert_eq!(finished["done"], true);
+            assert_eq!(finished["tick"], last["tick"]);
+
+            let reset = exchange(&mut reader, &mut writer, r#"{"cmd": "reset", "seed": 4}"#)?;
+            assert_eq!(reset["done"], false);
+            let observe = exchange(&mut reader, &mut writer, r#"{"cmd": "observe"}"#)?;
+            assert_eq!(observe["done"], false);
+
+            // The stand-in client then plays an episode through and quits
+            play(&mut reader, &mut writer, 1)
+        });
+
+        env::run(move |env| {
+            let mut reader = BufReader::new(server.try_clone().unwrap());
+            let mut writer = server.try_clone().unwrap();
+            serve_connection(env, &mut reader, &mut writer).unwrap();
+        });
+
+        controller.join().unwrap().unwrap();
+    }
+}
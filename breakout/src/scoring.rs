@@ -0,0 +1,262 @@
+use std::fs;
+use bevy::prelude::*;
+use bevy::render::camera::Camera;
+use bevy_rapier3d::physics::RigidBodyHandleComponent;
+use bevy_rapier3d::rapier::dynamics::RigidBodySet;
+use serde::Deserialize;
+use crate::{BallLost, Block, BlockDestroyed, Contacts, CurrentState, GameCamera, GameState, Paddle, Scoreboard};
+use crate::boss::BossPart;
+use crate::level::ResetLevel;
+use crate::window::ScaledText;
+
+const SCORING_RULES_PATH: &str = "assets/scoring.json";
+
+const POPUP_SECONDS: f32 = 0.8;
+const POPUP_RISE: f32 = 60.0;
+const POPUP_FONT_SIZE: f32 = 28.0;
+
+// Every scoring rule in one place; assets/scoring.json can override any of them
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct ScoringRules {
+    pub block_points: usize,
+    // Consecutive block hits needed to raise the multiplier by one
+    pub hits_per_multiplier_step: usize,
+    pub max_multiplier: usize,
+    pub row_clear_bonus: usize,
+    pub level_par_seconds: f32,
+    pub time_bonus_per_second: usize,
+    // For knocking out a drone with the ball, times the multiplier
+    pub drone_points: usize,
+    // Per point of damage to a boss, times the multiplier, and for beating it
+    pub boss_hit_points: usize,
+    pub boss_defeat_points: usize,
+}
+
+impl Default for ScoringRules {
+    fn default() -> Self {
+        ScoringRules {
+            block_points: 1,
+            hits_per_multiplier_step: 3,
+            max_multiplier: 8,
+            row_clear_bonus: 5,
+            level_par_seconds: 120.0,
+            time_bonus_per_second: 1,
+            drone_points: 10,
+            boss_hit_points: 5,
+            boss_defeat_points: 250,
+        }
+    }
+}
+
+impl ScoringRules {
+    pub fn load() -> Self {
+        match fs::read_to_string(SCORING_RULES_PATH) {
+            Ok(contents) => serde_json::from_str(&contents).unwrap_or_else(|e| {
+                eprintln!("Ignoring invalid {}: {}", SCORING_RULES_PATH, e);
+                ScoringRules::default()
+            }),
+            Err(_) => ScoringRules::default(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Combo {
+    pub hits: usize,
+    pub multiplier: usize,
+    level_seconds: f32,
+}
+
+impl Default for Combo {
+    fn default() -> Self {
+        Combo {
+            hits: 0,
+            multiplier: 1,
+            level_seconds: 0.0,
+        }
+    }
+}
+
+impl Combo {
+    fn reset(&mut self) {
+        self.hits = 0;
+        self.multiplier = 1;
+    }
+}
+
+pub struct PointsAwarded {
+    pub points: usize,
+    pub position: Vec3,
+}
+
+pub struct LevelCleared;
+
+pub fn scoring_system(
+    time: Res<Time>,
+    rules: Res<ScoringRules>,
+    current_state: Res<CurrentState>,
+    bodies: Res<RigidBodySet>,
+    mut scoreboard: ResMut<Scoreboard>,
+    mut combo: ResMut<Combo>,
+    mut points_events: ResMut<Events<PointsAwarded>>,
+    mut cleared_events: ResMut<Events<LevelCleared>>,
+    mut contact_reader: Local<EventReader<Contacts>>,
+    contact_events: Res<Events<Contacts>>,
+    mut destroyed_reader: Local<EventReader<BlockDestroyed>>,
+    destroyed_events: Res<Events<BlockDestroyed>>,
+    mut lost_reader: Local<EventReader<BallLost>>,
+    lost_events: Res<Events<BallLost>>,
+    mut reset_reader: Local<EventReader<ResetLevel>>,
+    reset_events: Res<Events<ResetLevel>>,
+    mut blocks: Query<(&Block, &RigidBodyHandleComponent)>,
+    paddles: Query<&Paddle>,
+    mut boss_parts: Query<&BossPart>,
+) {
+    if current_state.state == GameState::Playing {
+        combo.level_seconds += time.delta_seconds;
+    }
+
+    if reset_reader.iter(&reset_events).last().is_some() {
+        combo.reset();
+        combo.level_seconds = 0.0;
+    }
+
+    for _ in lost_reader.iter(&lost_events) {
+        combo.reset();
+    }
+
+    for contact in contact_reader.iter(&contact_events) {
+        if let Contacts::BallPaddle(_, paddle_entity) = contact {
+            combo.reset();
+            if let Ok(paddle) = paddles.get::<Paddle>(*paddle_entity) {
+                scoreboard.last_touch = paddle.player;
+            }
+        }
+    }
+
+    let mut cleared_any = false;
+    for block in destroyed_reader.iter(&destroyed_events) {
+        combo.hits += 1;
+        combo.multiplier = usize::min(
+            rules.max_multiplier,
+            1 + combo.hits / usize::max(1, rules.hits_per_multiplier_step),
+        );
+
+        let mut points = rules.block_points * combo.multiplier;
+
+        // Destroyed blocks have already left the physics world, so anything
+        // with a live body still counts as standing
+        let mut remaining = 0;
+        let mut remaining_in_row = 0;
+        for (standing, handle) in &mut blocks.iter() {
+            if !standing.kind.destructible() {
+                continue;
+            }
+            if let Some(body) = bodies.get(handle.handle()) {
+                remaining += 1;
+                if (body.position.translation.z - block.position.z()).abs() < 0.5 {
+                    remaining_in_row += 1;
+                }
+            }
+        }
+
+        if remaining_in_row == 0 {
+            points += rules.row_clear_bonus * combo.multiplier;
+        }
+
+        // On a boss level it's beating the boss that clears it, see boss.rs
+        let boss_standing = boss_parts.iter().iter().next().is_some();
+        if remaining == 0 && !cleared_any && !boss_standing {
+            cleared_any = true;
+            let seconds_under_par = f32::max(0.0, rules.level_par_seconds - combo.level_seconds);
+            points += seconds_under_par as usize * rules.time_bonus_per_second;
+            cleared_events.send(LevelCleared);
+        }
+
+        scoreboard.score += points;
+        let last_touch = scoreboard.last_touch;
+        scoreboard.players[last_touch] += points;
+        points_events.send(PointsAwarded {
+            points,
+            position: block.position,
+        });
+    }
+}
+
+pub struct ScorePopup {
+    seconds: f32,
+    position: Vec3,
+}
+
+pub fn spawn_score_popup_system(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut points_reader: Local<EventReader<PointsAwarded>>,
+    points_events: Res<Events<PointsAwarded>>,
+) {
+    for awarded in points_reader.iter(&points_events) {
+        commands
+            .spawn(TextComponents {
+                text: Text {
+                    font: asset_server.load("assets/fonts/FiraSans-Bold.ttf").unwrap(),
+                    value: format!("+{}", awarded.points),
+                    style: TextStyle {
+                        color: Color::rgb(2.3, 2.3, 0.0),
+                        font_size: POPUP_FONT_SIZE,
+                    },
+                },
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    ..Default::default()
+                },
+                ..Default::default()
+            })
+            .with(ScorePopup {
+                seconds: 0.0,
+                position: awarded.position,
+            })
+            .with(ScaledText { font_size: POPUP_FONT_SIZE });
+    }
+}
+
+// Pins each popup to its block's position on screen and floats it upwards
+pub fn score_popup_system(
+    mut commands: Commands,
+    time: Res<Time>,
+    windows: Res<Windows>,
+    mut cameras: Query<(&GameCamera, &Camera, &Transform)>,
+    mut popups: Query<(Entity, Mut<ScorePopup>, Mut<Style>)>,
+) {
+    let window = match windows.get_primary() {
+        Some(window) => window,
+        None => return,
+    };
+
+    let mut view_projection = None;
+    for (_game_camera, camera, transform) in &mut cameras.iter() {
+        view_projection = Some(camera.projection_matrix * transform.value.inverse());
+    }
+    let view_projection = match view_projection {
+        Some(view_projection) => view_projection,
+        None => return,
+    };
+
+    for (entity, mut popup, mut style) in &mut popups.iter() {
+        popup.seconds += time.delta_seconds;
+        if popup.seconds >= POPUP_SECONDS {
+            commands.despawn(entity);
+            continue;
+        }
+
+        let clip = view_projection * popup.position.extend(1.0);
+        if clip.w() <= 0.0 {
+            continue;
+        }
+        let x = (clip.x() / clip.w() + 1.0) / 2.0 * window.width as f32;
+        let y = (clip.y() / clip.w() + 1.0) / 2.0 * window.height as f32;
+
+        style.position.left = Val::Px(x);
+        style.position.bottom = Val::Px(y + POPUP_RISE * popup.seconds / POPUP_SECONDS);
+    }
+}
@@ -0,0 +1,81 @@
+use std::fs;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use crate::camera::CameraMode;
+
+const SETTINGS_PATH: &str = "settings.json";
+
+// Persisted player preferences. Missing fields fall back to their defaults so
+// older settings files keep loading as new options are added.
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub screen_shake: bool,
+    pub hit_stop: bool,
+    pub paddle_squash: bool,
+    pub block_flash: bool,
+    pub ball_trail: bool,
+    pub master_volume: f32,
+    pub impact_volume: f32,
+    pub block_volume: f32,
+    pub cue_volume: f32,
+    pub music_volume: f32,
+    pub music_muted: bool,
+    pub camera_mode: CameraMode,
+    pub fullscreen: bool,
+    // Co-op paddles each keep to their own half instead of sharing the edge
+    pub coop_split_halves: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            screen_shake: true,
+            hit_stop: true,
+            paddle_squash: true,
+            block_flash: true,
+            ball_trail: true,
+            master_volume: 1.0,
+            impact_volume: 0.7,
+            block_volume: 0.8,
+            cue_volume: 1.0,
+            music_volume: 0.5,
+            music_muted: false,
+            camera_mode: CameraMode::Overhead,
+            fullscreen: false,
+            coop_split_halves: true,
+        }
+    }
+}
+
+impl Settings {
+    pub fn load() -> Self {
+        match fs::read_to_string(SETTINGS_PATH) {
+            Ok(contents) => serde_json::from_str(&contents).unwrap_or_else(|e| {
+                eprintln!("Ignoring invalid {}: {}", SETTINGS_PATH, e);
+                Settings::default()
+            }),
+            Err(_) => Settings::default(),
+        }
+    }
+
+    pub fn save(&self) {
+        let contents = serde_json::to_string_pretty(self).unwrap();
+        if let Err(e) = fs::write(SETTINGS_PATH, contents) {
+            eprintln!("Unable to save settings to {}: {}", SETTINGS_PATH, e);
+        }
+    }
+}
+
+// F2 switches the motion heavy effects off (or back on) in one go
+pub fn settings_toggle_system(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut settings: ResMut<Settings>,
+) {
+    if keyboard_input.just_pressed(KeyCode::F2) {
+        let enabled = !(settings.screen_shake || settings.hit_stop);
+        settings.screen_shake = enabled;
+        settings.hit_stop = enabled;
+        settings.save();
+    }
+}
@@ -0,0 +1,727 @@
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use bevy::prelude::*;
+use bevy_rapier3d::physics::RigidBodyHandleComponent;
+use bevy_rapier3d::rapier::dynamics::RigidBodySet;
+use miniz_oxide::deflate::compress_to_vec;
+use miniz_oxide::inflate::decompress_to_vec;
+use crate::{
+    BallEntity, Block, CurrentState, GameState, Infoboard, Lives, Model, Paddle, RunTimer, Scoreboard,
+    MAX_PLAYERS,
+};
+use crate::highscores::HighScores;
+use crate::hud;
+use crate::level::Levels;
+use crate::modes::GameMode;
+use crate::motion::MovingBlock;
+use crate::powerups::ActivePowerUps;
+use crate::scoring::Combo;
+use crate::survival::{self, SurvivalHighScores};
+use crate::time_attack::TimeAttack;
+use crate::window;
+
+pub const DEFAULT_ADDRESS: &str = "127.0.0.1:7500";
+
+// Positions travel as hundredths of a unit in an i16, plenty for a board
+// 80 units long
+const POSITION_SCALE: f32 = 100.0;
+const COMPRESSION_LEVEL: u8 = 6;
+
+const KEYFRAME: u8 = 0;
+const TICK: u8 = 1;
+
+struct BlockInfo {
+    position: Vec3,
+    color: Color,
+}
+
+// What a spectator needs to draw one frame. The block list itself only goes
+// out in keyframes; ticks carry one bit per keyframe block for whether it's
+// still standing, and where the moving ones have got to.
+struct TickState {
+    state: GameState,
+    score: usize,
+    players: [usize; MAX_PLAYERS],
+    lives: [usize; MAX_PLAYERS],
+    run_seconds: f32,
+    multiplier: usize,
+    ball: Vec3,
+    paddles: Vec<(usize, Vec3)>,
+    standing: Vec<bool>,
+    moved: Vec<(usize, Vec3)>,
+}
+
+enum Message {
+    Keyframe {
+        mode: GameMode,
+        level: usize,
+        blocks: Vec<BlockInfo>,
+    },
+    Tick(TickState),
+}
+
+fn game_state_from(byte: u8) -> GameState {
+    match byte {
+        1 => GameState::Playing,
+        2 => GameState::Paused,
+        3 => GameState::GameOver,
+        4 => GameState::Editing,
+        _ => GameState::ArenaStart,
+    }
+}
+
+fn game_mode_from(byte: u8) -> GameMode {
+    match byte {
+        1 => GameMode::Versus,
+        2 => GameMode::Coop,
+        3 => GameMode::Endless,
+        4 => GameMode::Daily,
+        5 => GameMode::TimeAttack,
+        6 => GameMode::Survival,
+        _ => GameMode::Classic,
+    }
+}
+
+fn push_position(bytes: &mut Vec<u8>, position: Vec3) {
+    for value in [position.x(), position.y(), position.z()].iter() {
+        bytes.extend_from_slice(&((value * POSITION_SCALE).round() as i16).to_le_bytes());
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    at: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        let slice = self.bytes.get(self.at..self.at + len)?;
+        self.at += len;
+        Some(slice)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        self.take(1).map(|bytes| bytes[0])
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        self.take(2).map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        self.take(4).map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn f32(&mut self) -> Option<f32> {
+        self.u32().map(f32::from_bits)
+    }
+
+    fn position(&mut self) -> Option<Vec3> {
+        let mut values = [0.0; 3];
+        for value in values.iter_mut() {
+            let bytes = self.take(2)?;
+            *value = i16::from_le_bytes([bytes[0], bytes[1]]) as f32 / POSITION_SCALE;
+        }
+        Some(Vec3::new(values[0], values[1], values[2]))
+    }
+}
+
+impl Message {
+    fn encode(&self) -> Vec<u8> {
+        let mut bytes = vec![];
+        match self {
+            Message::Keyframe { mode, level, blocks } => {
+                bytes.push(KEYFRAME);
+                bytes.push(*mode as u8);
+                bytes.push(*level as u8);
+                bytes.extend_from_slice(&(blocks.len() as u16).to_le_bytes());
+                for block in blocks.iter() {
+                    push_position(&mut bytes, block.position);
+                    // Level files can use any color, so channels go as they are
+                    for channel in [block.color.r, block.color.g, block.color.b].iter() {
+                        bytes.extend_from_slice(&channel.to_le_bytes());
+                    }
+                }
+            },
+            Message::Tick(tick) => {
+                bytes.push(TICK);
+                bytes.push(tick.state as u8);
+                bytes.extend_from_slice(&(tick.score as u32).to_le_bytes());
+                for player in 0..MAX_PLAYERS {
+                    bytes.extend_from_slice(&(tick.players[player] as u32).to_le_bytes());
+                    bytes.push(tick.lives[player] as u8);
+                }
+                bytes.extend_from_slice(&((tick.run_seconds * 1000.0) as u32).to_le_bytes());
+                bytes.push(tick.multiplier as u8);
+                push_position(&mut bytes, tick.ball);
+                bytes.push(tick.paddles.len() as u8);
+                for (player, position) in tick.paddles.iter() {
+                    bytes.push(*player as u8);
+                    push_position(&mut bytes, *position);
+                }
+                for chunk in tick.standing.chunks(8) {
+                    let mut byte = 0;
+                    for (bit, standing) in chunk.iter().enumerate() {
+                        if *standing {
+                            byte |= 1 << bit;
+                        }
+                    }
+                    bytes.push(byte);
+                }
+                bytes.extend_from_slice(&(tick.moved.len() as u16).to_le_bytes());
+                for (index, position) in tick.moved.iter() {
+                    bytes.extend_from_slice(&(*index as u16).to_le_bytes());
+                    push_position(&mut bytes, *position);
+                }
+            },
+        }
+        bytes
+    }
+
+    // Ticks are decoded against the current keyframe's block count
+    fn decode(bytes: &[u8], block_count: usize) -> Option<Message> {
+        let mut reader = Reader { bytes, at: 0 };
+        match reader.u8()? {
+            KEYFRAME => {
+                let mode = game_mode_from(reader.u8()?);
+                let level = reader.u8()? as usize;
+                let count = reader.u16()? as usize;
+                let mut blocks = vec![];
+                for _ in 0..count {
+                    let position = reader.position()?;
+                    let (r, g, b) = (reader.f32()?, reader.f32()?, reader.f32()?);
+                    blocks.push(BlockInfo {
+                        position,
+                        color: Color::rgb(r, g, b),
+                    });
+                }
+                Some(Message::Keyframe { mode, level, blocks })
+            },
+            TICK => {
+                let state = game_state_from(reader.u8()?);
+                let score = reader.u32()? as usize;
+                let mut players = [0; MAX_PLAYERS];
+                let mut lives = [0; MAX_PLAYERS];
+                for player in 0..MAX_PLAYERS {
+                    players[player] = reader.u32()? as usize;
+                    lives[player] = reader.u8()? as usize;
+                }
+                let run_seconds = reader.u32()? as f32 / 1000.0;
+                let multiplier = reader.u8()? as usize;
+                let ball = reader.position()?;
+                let mut paddles = vec![];
+                for _ in 0..reader.u8()? {
+                    let player = reader.u8()? as usize;
+                    paddles.push((player, reader.position()?));
+                }
+                let mask = reader.take((block_count + 7) / 8)?;
+                let standing = (0..block_count).map(|index| mask[index / 8] & (1 << (index % 8)) != 0).collect();
+                let mut moved = vec![];
+                for _ in 0..reader.u16()? {
+                    let index = reader.u16()? as usize;
+                    moved.push((index, reader.position()?));
+                }
+                Some(Message::Tick(TickState {
+                    state,
+                    score,
+                    players,
+                    lives,
+                    run_seconds,
+                    multiplier,
+                    ball,
+                    paddles,
+                    standing,
+                    moved,
+                }))
+            },
+            _ => None,
+        }
+    }
+}
+
+// Each message is deflated and sent with a u32 length prefix
+fn frame(message: &Message) -> Vec<u8> {
+    let compressed = compress_to_vec(&message.encode(), COMPRESSION_LEVEL);
+    let mut bytes = (compressed.len() as u32).to_le_bytes().to_vec();
+    bytes.extend_from_slice(&compressed);
+    bytes
+}
+
+// - Host -
+
+struct Spectator {
+    stream: TcpStream,
+    needs_keyframe: bool,
+    // Framed messages the socket hasn't taken yet
+    unsent: Vec<u8>,
+}
+
+impl Spectator {
+    // Writes what the socket will take without blocking. False once the
+    // spectator has gone.
+    fn flush(&mut self) -> bool {
+        while !self.unsent.is_empty() {
+            match (&self.stream).write(&self.unsent) {
+                Ok(0) => return false,
+                Ok(sent) => {
+                    self.unsent.drain(..sent);
+                },
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return true,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => (),
+                Err(_) => return false,
+            }
+        }
+        true
+    }
+}
+
+// Disabled unless the game was started with --spectate-host
+#[derive(Default)]
+pub struct SpectatorHost {
+    listener: Option<TcpListener>,
+    spectators: Vec<Spectator>,
+    // The keyframe's blocks, in the order of the ticks' standing bits
+    blocks: Vec<Entity>,
+    keyframe: Vec<u8>,
+}
+
+impl SpectatorHost {
+    pub fn bind(address: &str) -> Self {
+        let listener = TcpListener::bind(address).and_then(|listener| {
+            listener.set_nonblocking(true)?;
+            Ok(listener)
+        });
+        match listener {
+            Ok(listener) => {
+                eprintln!("Spectators can connect to {}", address);
+                SpectatorHost {
+                    listener: Some(listener),
+                    ..Default::default()
+                }
+            },
+            Err(e) => {
+                eprintln!("Unable to host spectators on {}: {}", address, e);
+                SpectatorHost::default()
+            },
+        }
+    }
+}
+
+pub fn spectator_host_system(
+    mut host: ResMut<SpectatorHost>,
+    mode: Res<GameMode>,
+    levels: Res<Levels>,
+    current_state: Res<CurrentState>,
+    scoreboard: Res<Scoreboard>,
+    lives: Res<Lives>,
+    run_timer: Res<RunTimer>,
+    combo: Res<Combo>,
+    bodies: Res<RigidBodySet>,
+    ball_entity: Res<BallEntity>,
+    mut new_blocks: Query<(&Block, Added<RigidBodyHandleComponent>)>,
+    mut blocks: Query<(Entity, &Block, &Model, &RigidBodyHandleComponent)>,
+    mut paddles: Query<(&Paddle, &RigidBodyHandleComponent)>,
+    handles: Query<&RigidBodyHandleComponent>,
+    moving_blocks: Query<&MovingBlock>,
+) {
+    let host = &mut *host;
+    let listener = match &host.listener {
+        Some(listener) => listener,
+        None => return,
+    };
+
+    while let Ok((stream, address)) = listener.accept() {
+        if stream.set_nonblocking(true).is_ok() {
+            let _ = stream.set_nodelay(true);
+            eprintln!("Spectator connected from {}", address);
+            host.spectators.push(Spectator {
+                stream,
+                needs_keyframe: true,
+                unsent: vec![],
+            });
+        }
+    }
+    if host.spectators.is_empty() {
+        return;
+    }
+
+    let position_of = |handle: &RigidBodyHandleComponent| {
+        bodies.get(handle.handle()).map(|body| {
+            let translation = body.position.translation;
+            Vec3::new(translation.x, translation.y, translation.z)
+        })
+    };
+
+    // A fresh set of blocks has just made it into the physics world
+    let rebuilt = new_blocks.iter().iter().next().is_some();
+    if rebuilt || host.keyframe.is_empty() {
+        host.blocks.clear();
+        let mut infos = vec![];
+        for (entity, _block, model, handle) in &mut blocks.iter() {
+            if let Some(position) = position_of(handle) {
+                host.blocks.push(entity);
+                infos.push(BlockInfo {
+                    position,
+                    color: model.color,
+                });
+            }
+        }
+        host.keyframe = frame(&Message::Keyframe {
+            mode: *mode,
+            level: levels.current,
+            blocks: infos,
+        });
+        for spectator in host.spectators.iter_mut() {
+            spectator.needs_keyframe = true;
+        }
+    }
+
+    let mut tick_paddles = vec![];
+    for (paddle, handle) in &mut paddles.iter() {
+        if let Some(position) = position_of(handle) {
+            tick_paddles.push((paddle.player, position));
+        }
+    }
+    let standing = host
+        .blocks
+        .iter()
+        .map(|entity| match blocks.get::<RigidBodyHandleComponent>(*entity) {
+            Ok(handle) => bodies.get(handle.handle()).is_some(),
+            Err(_) => false,
+        })
+        .collect();
+    let mut moved = vec![];
+    for (index, entity) in host.blocks.iter().enumerate() {
+        if moving_blocks.get::<MovingBlock>(*entity).is_err() {
+            continue;
+        }
+        if let Some(position) = blocks.get::<RigidBodyHandleComponent>(*entity).ok().and_then(|handle| position_of(&handle)) {
+            moved.push((index, position));
+        }
+    }
+    let tick = frame(&Message::Tick(TickState {
+        state: current_state.state,
+        score: scoreboard.score,
+        players: scoreboard.players,
+        lives: lives.count,
+        run_seconds: run_timer.seconds,
+        multiplier: combo.multiplier,
+        ball: handles
+            .get::<RigidBodyHandleComponent>(ball_entity.0)
+            .ok()
+            .and_then(|handle| position_of(&handle))
+            .unwrap_or(Vec3::zero()),
+        paddles: tick_paddles,
+        standing,
+        moved,
+    }));
+
+    // Only whole messages are queued, and only once the last ones have gone
+    // out, so a spectator that can't keep up skips ticks rather than stalling
+    // the game. Ticks carry the full state, so skipping one loses nothing.
+    let keyframe = &host.keyframe;
+    for spectator in host.spectators.iter_mut() {
+        if spectator.unsent.is_empty() {
+            if spectator.needs_keyframe {
+                spectator.unsent.extend_from_slice(keyframe);
+                spectator.needs_keyframe = false;
+            }
+            spectator.unsent.extend_from_slice(&tick);
+        }
+    }
+    let spectators = std::mem::take(&mut host.spectators);
+    host.spectators = spectators
+        .into_iter()
+        .filter_map(|mut spectator| if spectator.flush() { Some(spectator) } else { None })
+        .collect();
+}
+
+// - Spectator Client -
+
+pub struct SpectatorClient {
+    stream: Option<TcpStream>,
+    buffer: Vec<u8>,
+    ball: Option<Entity>,
+    paddles: [Option<Entity>; MAX_PLAYERS],
+    blocks: Vec<Option<Entity>>,
+    top_wall: Option<Entity>,
+}
+
+pub fn spawn_model(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    materials: &mut Assets<StandardMaterial>,
+    mesh: &'static str,
+    color: Color,
+    translation: Vec3,
+    rotation: Quat,
+) -> Entity {
+    let entity = Entity::new();
+    commands.spawn_as_entity(
+        entity,
+        PbrComponents {
+            mesh: asset_server.load(mesh).unwrap(),
+            material: materials.add(color.into()),
+            translation: Translation(translation),
+            rotation: Rotation(rotation),
+            ..Default::default()
+        },
+    );
+    entity
+}
+
+// Renders a match hosted elsewhere; nothing is simulated locally
+pub fn run_client(address: String) {
+    let stream = match TcpStream::connect(&address).and_then(|stream| {
+        stream.set_nonblocking(true)?;
+        Ok(stream)
+    }) {
+        Ok(stream) => stream,
+        Err(e) => {
+            eprintln!("Unable to connect to {}: {}", address, e);
+            return;
+        },
+    };
+
+    App::build()
+        .add_resource(Msaa { samples: 4 })
+        .add_resource(WindowDescriptor {
+            title: "Breakout - Spectating".to_string(),
+            width: 1920,
+            height: 1080,
+            vsync: true,
+            resizable: true,
+            ..Default::default()
+        })
+        .add_resource(SpectatorClient {
+            stream: Some(stream),
+            buffer: vec![],
+            ball: None,
+            paddles: [None; MAX_PLAYERS],
+            blocks: vec![],
+            top_wall: None,
+        })
+        .add_resource(Scoreboard::default())
+        .add_resource(Lives { count: [0; MAX_PLAYERS] })
+        .add_resource(RunTimer { seconds: 0.0 })
+        .add_resource(ActivePowerUps::default())
+        .add_resource(Combo::default())
+        .add_resource(CurrentState::new(GameState::ArenaStart))
+        .add_resource(Levels::builtin())
+        .add_resource(GameMode::Classic)
+        .add_resource(HighScores::load("highscores.txt"))
+        .add_resource(TimeAttack::load())
+        .add_resource(SurvivalHighScores(HighScores::load(survival::SURVIVAL_HIGH_SCORES_PATH)))
+        .add_startup_system(crate::setup.system())
+        .add_startup_system(hud::setup_hud.system())
+        .add_startup_system(setup_spectator_arena.system())
+        .add_system(spectator_client_system.system())
+        .add_system(hud::hud_system.system())
+        .add_system(hud::hud_timer_system.system())
+        .add_system(window::ui_scale_system.system())
+        .add_default_plugins()
+        .run();
+}
+
+pub fn setup_spectator_arena(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    let blue = Color::rgb(0.0, 0.0, 2.04);
+    for x in [31.5, -31.5].iter() {
+        spawn_model(
+            &mut commands,
+            &asset_server,
+            &mut materials,
+            "assets/blender/wall/export/wall.gltf",
+            blue,
+            Vec3::new(*x, 1.0, 0.0),
+            Quat::identity(),
+        );
+    }
+    spawn_model(
+        &mut commands,
+        &asset_server,
+        &mut materials,
+        "assets/blender/board/export/board.gltf",
+        blue,
+        Vec3::zero(),
+        Quat::identity(),
+    );
+}
+
+pub fn spectator_client_system(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut client: ResMut<SpectatorClient>,
+    mut current_state: ResMut<CurrentState>,
+    mut mode: ResMut<GameMode>,
+    mut levels: ResMut<Levels>,
+    mut scoreboard: ResMut<Scoreboard>,
+    mut lives: ResMut<Lives>,
+    mut run_timer: ResMut<RunTimer>,
+    mut combo: ResMut<Combo>,
+    mut translations: Query<Mut<Translation>>,
+    mut infoboard: Query<(Mut<Text>, &Infoboard)>,
+) {
+    let client = &mut *client;
+
+    let mut connected = false;
+    if let Some(stream) = &mut client.stream {
+        let mut chunk = [0; 4096];
+        connected = loop {
+            match stream.read(&mut chunk) {
+                Ok(0) => break false,
+                Ok(len) => client.buffer.extend_from_slice(&chunk[..len]),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break true,
+                Err(_) => break false,
+            }
+        };
+    }
+    if !connected {
+        client.stream = None;
+    }
+
+    for (mut text, _infoboard) in &mut infoboard.iter() {
+        text.value = if !connected {
+            "SPECTATING - Connection lost".to_string()
+        } else if client.blocks.is_empty() && client.ball.is_none() {
+            "SPECTATING - Waiting for the match".to_string()
+        } else {
+            match current_state.state {
+                GameState::Paused => "SPECTATING - Paused".to_string(),
+                GameState::ArenaStart => "SPECTATING - Waiting for the serve".to_string(),
+                _ => "SPECTATING".to_string(),
+            }
+        };
+    }
+
+    let mut messages = vec![];
+    while client.buffer.len() >= 4 {
+        let len = u32::from_le_bytes([client.buffer[0], client.buffer[1], client.buffer[2], client.buffer[3]]) as usize;
+        if client.buffer.len() < 4 + len {
+            break;
+        }
+        let payload: Vec<u8> = client.buffer.drain(..4 + len).skip(4).collect();
+        if let Ok(bytes) = decompress_to_vec(&payload) {
+            messages.push(bytes);
+        }
+    }
+
+    for bytes in messages {
+        match Message::decode(&bytes, client.blocks.len()) {
+            Some(Message::Keyframe { mode: new_mode, level, blocks }) => {
+                for entity in client.blocks.drain(..).flatten() {
+                    commands.despawn(entity);
+                }
+                for info in blocks {
+                    client.blocks.push(Some(spawn_model(
+                        &mut commands,
+                        &asset_server,
+                        &mut materials,
+                        "assets/blender/block/export/block.gltf",
+                        info.color,
+                        info.position,
+                        Quat::identity(),
+                    )));
+                }
+
+                *mode = new_mode;
+                levels.current = usize::min(level, levels.levels.len() - 1);
+                if new_mode == GameMode::Versus {
+                    if let Some(entity) = client.top_wall.take() {
+                        commands.despawn(entity);
+                    }
+                } else if client.top_wall.is_none() {
+                    client.top_wall = Some(spawn_model(
+                        &mut commands,
+                        &asset_server,
+                        &mut materials,
+                        "assets/blender/top_wall/export/top_wall.gltf",
+                        Color::rgb(0.0, 0.0, 2.04),
+                        Vec3::new(0.0, 1.0, 39.0),
+                        Quat::from_rotation_y(1.57),
+                    ));
+                }
+            },
+            Some(Message::Tick(tick)) => {
+                current_state.state = tick.state;
+                scoreboard.score = tick.score;
+                scoreboard.players = tick.players;
+                lives.count = tick.lives;
+                run_timer.seconds = tick.run_seconds;
+                combo.multiplier = tick.multiplier;
+
+                match client.ball {
+                    Some(entity) => {
+                        if let Ok(mut translation) = translations.get_mut::<Translation>(entity) {
+                            translation.0 = tick.ball;
+                        }
+                    },
+                    None => {
+                        client.ball = Some(spawn_model(
+                            &mut commands,
+                            &asset_server,
+                            &mut materials,
+                            "assets/blender/ball/export/ball.gltf",
+                            Color::rgb(0.7, 0.0, 0.0),
+                            tick.ball,
+                            Quat::identity(),
+                        ));
+                    },
+                }
+
+                let mut seen = [false; MAX_PLAYERS];
+                for (player, position) in tick.paddles {
+                    if player >= MAX_PLAYERS {
+                        continue;
+                    }
+                    seen[player] = true;
+                    match client.paddles[player] {
+                        Some(entity) => {
+                            if let Ok(mut translation) = translations.get_mut::<Translation>(entity) {
+                                translation.0 = position;
+                            }
+                        },
+                        None => {
+                            client.paddles[player] = Some(spawn_model(
+                                &mut commands,
+                                &asset_server,
+                                &mut materials,
+                                "assets/blender/paddle/export/paddle.gltf",
+                                Color::rgb(0.9, 0.92, 1.0),
+                                position,
+                                Quat::identity(),
+                            ));
+                        },
+                    }
+                }
+                for player in 0..MAX_PLAYERS {
+                    if !seen[player] {
+                        if let Some(entity) = client.paddles[player].take() {
+                            commands.despawn(entity);
+                        }
+                    }
+                }
+
+                for (index, position) in tick.moved {
+                    if let Some(Some(entity)) = client.blocks.get(index) {
+                        if let Ok(mut translation) = translations.get_mut::<Translation>(*entity) {
+                            translation.0 = position;
+                        }
+                    }
+                }
+                for (slot, standing) in client.blocks.iter_mut().zip(tick.standing) {
+                    if !standing {
+                        if let Some(entity) = slot.take() {
+                            commands.despawn(entity);
+                        }
+                    }
+                }
+            },
+            None => (),
+        }
+    }
+}
@@ -0,0 +1,253 @@
+use bevy::prelude::*;
+use bevy_rapier3d::physics::RigidBodyHandleComponent;
+use bevy_rapier3d::rapier::dynamics::{RigidBodySet, JointSet};
+use bevy_rapier3d::rapier::geometry::{BroadPhase, NarrowPhase, ColliderSet};
+use bevy_rapier3d::rapier::pipeline::PhysicsPipeline;
+use crate::{Block, CurrentState, GameState, Model, RunOver};
+use crate::generator;
+use crate::highscores::HighScores;
+use crate::level::{self, BlockKind, BlockSpec, Level, Levels, ResetLevel, DEFAULT_BALL_SPEED};
+use crate::modes::GameMode;
+use crate::rng::Rng;
+
+pub const SURVIVAL_HIGH_SCORES_PATH: &str = "survival_highscores.txt";
+
+// New rows come in at the top of the field and everything moves down a row
+const TOP_ROW_Z: f32 = 35.0;
+const ROW_SPACING: f32 = 5.0;
+const COLUMNS: usize = 6;
+const FIRST_X: f32 = -25.0;
+const COLUMN_SPACING: f32 = 10.0;
+const STARTING_ROWS: usize = 3;
+
+// A block past this line ends the run; the serve comes from z = -20
+const DANGER_Z: f32 = -12.5;
+
+// The rows advance every 12 seconds at first, a second sooner for every 20
+// seconds survived, down to one advance every 3 seconds
+const START_ADVANCE_SECONDS: f32 = 12.0;
+const MIN_ADVANCE_SECONDS: f32 = 3.0;
+const SPEED_UP_SECONDS: f32 = 20.0;
+
+// Rows get fuller and tougher over the first few minutes
+const FULL_DIFFICULTY_SECONDS: f32 = 180.0;
+
+// The high score table for survival runs, kept apart from the campaign's
+pub struct SurvivalHighScores(pub HighScores);
+
+pub struct Survival {
+    rng: Rng,
+    pub seconds: f32,
+    since_advance: f32,
+    rows_spawned: usize,
+    advance_pending: bool,
+}
+
+impl Survival {
+    pub fn new(seed: u64) -> Self {
+        Survival {
+            rng: Rng::new(seed),
+            seconds: 0.0,
+            since_advance: 0.0,
+            rows_spawned: 0,
+            advance_pending: false,
+        }
+    }
+
+    fn advance_seconds(&self) -> f32 {
+        f32::max(MIN_ADVANCE_SECONDS, START_ADVANCE_SECONDS - self.seconds / SPEED_UP_SECONDS)
+    }
+
+    fn row(&mut self, z: f32) -> Vec<BlockSpec> {
+        let difficulty = f32::min(1.0, self.seconds / FULL_DIFFICULTY_SECONDS);
+        let color = generator::hsv(self.rows_spawned as f32 * 0.09, 0.85, 2.3);
+        self.rows_spawned += 1;
+
+        let mut row = vec![];
+        for column in 0..COLUMNS {
+            if self.rng.chance(0.6 + 0.35 * difficulty) {
+                let tough = self.rng.chance(0.1 + 0.4 * difficulty);
+                row.push(BlockSpec {
+                    x: FIRST_X + column as f32 * COLUMN_SPACING,
+                    z,
+                    color,
+                    kind: if tough { BlockKind::Tough } else { BlockKind::Standard },
+                    motion: None,
+                });
+            }
+        }
+        row
+    }
+
+    fn start(&mut self) -> Level {
+        self.seconds = 0.0;
+        self.since_advance = 0.0;
+        self.rows_spawned = 0;
+        self.advance_pending = false;
+
+        let mut blocks = vec![];
+        for row in 0..STARTING_ROWS {
+            let z = TOP_ROW_Z - (STARTING_ROWS - 1 - row) as f32 * ROW_SPACING;
+            blocks.extend(self.row(z));
+        }
+        Level {
+            name: "Survival".to_string(),
+            music: Some("checkerboard".to_string()),
+            ball_speed: DEFAULT_BALL_SPEED,
+            blocks,
+            drones: vec![],
+            boss: None,
+            path: None,
+            unsaved: false,
+        }
+    }
+}
+
+// Starts the field over on entry and after every run, keeps the advance
+// clock, and ends the run once a block gets past the danger line
+pub fn survival_system(
+    time: Res<Time>,
+    mode: Res<GameMode>,
+    bodies: Res<RigidBodySet>,
+    mut survival: ResMut<Survival>,
+    mut high_scores: ResMut<SurvivalHighScores>,
+    mut levels: ResMut<Levels>,
+    mut current_state: ResMut<CurrentState>,
+    mut applied: Local<Option<GameMode>>,
+    mut run_reader: Local<EventReader<RunOver>>,
+    run_events: Res<Events<RunOver>>,
+    mut reset_events: ResMut<Events<ResetLevel>>,
+    mut blocks: Query<(&Block, &RigidBodyHandleComponent)>,
+) {
+    let entered = *mode == GameMode::Survival && *applied != Some(GameMode::Survival);
+    *applied = Some(*mode);
+
+    let run_over = run_reader.iter(&run_events).last().cloned();
+    if *mode != GameMode::Survival {
+        return;
+    }
+
+    if let Some(run) = run_over {
+        high_scores.0.record(run.score);
+    }
+    if entered || run_over.is_some() {
+        levels.survival = survival.start();
+        reset_events.send(ResetLevel);
+        return;
+    }
+
+    if current_state.state != GameState::Playing {
+        return;
+    }
+
+    survival.seconds += time.delta_seconds;
+    survival.since_advance += time.delta_seconds;
+    if survival.since_advance >= survival.advance_seconds() {
+        survival.since_advance = 0.0;
+        survival.advance_pending = true;
+    }
+
+    for (_block, handle) in &mut blocks.iter() {
+        if let Some(body) = bodies.get(handle.handle()) {
+            if body.position.translation.z < DANGER_Z {
+                current_state.set(GameState::GameOver);
+                break;
+            }
+        }
+    }
+}
+
+// Moves every standing block down a row and brings in a new row at the top.
+// The blocks are rebuilt in their new places, keeping any damage taken.
+pub fn survival_advance_system(
+    mut commands: Commands,
+    mut survival: ResMut<Survival>,
+    mut pipeline: ResMut<PhysicsPipeline>,
+    mut broad_phase: ResMut<BroadPhase>,
+    mut narrow_phase: ResMut<NarrowPhase>,
+    mut bodies: ResMut<RigidBodySet>,
+    mut colliders: ResMut<ColliderSet>,
+    mut joints: ResMut<JointSet>,
+    mut blocks: Query<(Entity, &Block, &Model, &RigidBodyHandleComponent)>,
+) {
+    if !survival.advance_pending {
+        return;
+    }
+    survival.advance_pending = false;
+
+    let mut moved = vec![];
+    for (entity, block, model, handle) in &mut blocks.iter() {
+        let translation = match bodies.get(handle.handle()) {
+            Some(body) => body.position.translation,
+            None => continue,
+        };
+        moved.push((
+            BlockSpec {
+                x: translation.x,
+                z: translation.z - ROW_SPACING,
+                color: model.color,
+                kind: block.kind,
+                motion: None,
+            },
+            block.hits_left,
+        ));
+
+        pipeline.remove_rigid_body(
+            handle.handle(),
+            &mut broad_phase,
+            &mut narrow_phase,
+            &mut bodies,
+            &mut colliders,
+            &mut joints,
+        );
+        commands.despawn(entity);
+    }
+
+    for (spec, hits_left) in moved {
+        let mut bundle = level::block_bundle(&spec);
+        bundle.3.hits_left = hits_left;
+        commands.spawn(bundle);
+    }
+    for spec in survival.row(TOP_ROW_Z) {
+        level::spawn_block(&mut commands, &spec);
+    }
+}
+
+pub struct DangerMarker;
+
+// Red strips across the board along the danger line while survival is on
+pub fn danger_line_system(
+    mut commands: Commands,
+    mode: Res<GameMode>,
+    asset_server: Res<AssetServer>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut markers: Query<(Entity, &DangerMarker)>,
+) {
+    let mut shown = false;
+    for (entity, _marker) in &mut markers.iter() {
+        if *mode == GameMode::Survival {
+            shown = true;
+        } else {
+            commands.despawn(entity);
+        }
+    }
+
+    if *mode != GameMode::Survival || shown {
+        return;
+    }
+
+    let material = materials.add(Color::rgb(2.3, 0.0, 0.0).into());
+    for column in 0..COLUMNS {
+        commands
+            .spawn(PbrComponents {
+                mesh: asset_server
+                    .load("assets/blender/block/export/block.gltf")
+                    .unwrap(),
+                material,
+                translation: Translation(Vec3::new(FIRST_X + column as f32 * COLUMN_SPACING, 2.1, DANGER_Z)),
+                ..Default::default()
+            })
+            .with(NonUniformScale(Vec3::new(1.2, 0.1, 0.25)))
+            .with(DangerMarker);
+    }
+}
@@ -0,0 +1,317 @@
+use std::collections::HashMap;
+use std::f32::consts::PI;
+use std::fs;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use crate::{BallLost, CurrentState, GameState, Paddle, PaddleInput, PADDLE_MAX_X};
+use crate::level::{Levels, ResetLevel};
+use crate::modes::GameMode;
+use crate::scoring::LevelCleared;
+
+const BEST_TIMES_PATH: &str = "best_times.json";
+
+// Losing the ball puts this much on the clock instead of costing a life
+const BALL_LOST_PENALTY_SECONDS: f32 = 5.0;
+
+// The ghost runs just behind the real paddle so the two never overlap
+const GHOST_Z_OFFSET: f32 = -2.5;
+const GHOST_HIDDEN_Y: f32 = -100.0;
+
+// Everything needed to play a run's paddle back: each change of direction and
+// each ball loss (which sends the paddle home), against the clock without
+// penalties
+#[derive(Clone, Default, Serialize, Deserialize)]
+struct Recording {
+    seconds: f32,
+    inputs: Vec<(f32, i8)>,
+    resets: Vec<f32>,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct BestTimes {
+    levels: HashMap<String, Recording>,
+}
+
+// Replays a recording up to a point on the clock, piece by piece so it lands
+// in the same place whatever the frame rate
+#[derive(Default)]
+struct Ghost {
+    seconds: f32,
+    x: f32,
+    direction: i8,
+    next_input: usize,
+    next_reset: usize,
+}
+
+impl Ghost {
+    fn advance(&mut self, recording: &Recording, until: f32, speed: f32) {
+        while self.seconds < until {
+            let next_input = recording.inputs.get(self.next_input).map_or(f32::MAX, |input| input.0);
+            let next_reset = recording.resets.get(self.next_reset).cloned().unwrap_or(f32::MAX);
+            let boundary = f32::min(until, f32::min(next_input, next_reset));
+
+            let step = f32::max(0.0, boundary - self.seconds);
+            self.x += step * self.direction as f32 * speed;
+            self.x = f32::max(-PADDLE_MAX_X, f32::min(PADDLE_MAX_X, self.x));
+            self.seconds = boundary;
+
+            if next_input <= boundary {
+                self.direction = recording.inputs[self.next_input].1;
+                self.next_input += 1;
+            }
+            if next_reset <= boundary {
+                self.x = 0.0;
+                self.next_reset += 1;
+            }
+        }
+    }
+}
+
+pub struct TimeAttack {
+    best_times: BestTimes,
+    pub play_seconds: f32,
+    pub penalty_seconds: f32,
+    recording: Recording,
+    last_direction: i8,
+    ghost: Ghost,
+    // Shown on the start screen after a level is cleared
+    pub result: Option<String>,
+}
+
+pub fn format_time(seconds: f32) -> String {
+    let hundredths = (seconds * 100.0).round() as usize;
+    format!("{:02}:{:02}.{:02}", hundredths / 6000, hundredths / 100 % 60, hundredths % 100)
+}
+
+impl TimeAttack {
+    pub fn load() -> Self {
+        let best_times = match fs::read_to_string(BEST_TIMES_PATH) {
+            Ok(contents) => serde_json::from_str(&contents).unwrap_or_else(|e| {
+                eprintln!("Ignoring invalid {}: {}", BEST_TIMES_PATH, e);
+                BestTimes::default()
+            }),
+            Err(_) => BestTimes::default(),
+        };
+
+        TimeAttack {
+            best_times,
+            play_seconds: 0.0,
+            penalty_seconds: 0.0,
+            recording: Recording::default(),
+            last_direction: 0,
+            ghost: Ghost::default(),
+            result: None,
+        }
+    }
+
+    pub fn total_seconds(&self) -> f32 {
+        self.play_seconds + self.penalty_seconds
+    }
+
+    pub fn best(&self, level_name: &str) -> Option<f32> {
+        self.best_times.levels.get(level_name).map(|recording| recording.seconds)
+    }
+
+    pub fn banner(&self, levels: &Levels) -> String {
+        let mut banner = String::new();
+        if let Some(result) = &self.result {
+            banner += result;
+            banner += "\n\n";
+        }
+        let name = &levels.current().name;
+        let best = self.best(name).map_or("--:--.--".to_string(), format_time);
+        banner += &format!("{}   Best {}   [ ] Change level\n", name, best);
+        banner
+    }
+
+    fn start_attempt(&mut self) {
+        self.play_seconds = 0.0;
+        self.penalty_seconds = 0.0;
+        self.recording = Recording::default();
+        self.last_direction = 0;
+        self.ghost = Ghost::default();
+    }
+
+    fn finish(&mut self, level_name: &str) -> String {
+        let seconds = self.total_seconds();
+        self.recording.seconds = seconds;
+        let result = match self.best(level_name) {
+            Some(best) if best <= seconds => format!(
+                "TIME {} - {} behind the best {}",
+                format_time(seconds),
+                format_time(seconds - best),
+                format_time(best),
+            ),
+            Some(best) => {
+                let result = format!("NEW BEST {} - {} faster", format_time(seconds), format_time(best - seconds));
+                self.best_times.levels.insert(level_name.to_string(), self.recording.clone());
+                self.save();
+                result
+            },
+            None => {
+                self.best_times.levels.insert(level_name.to_string(), self.recording.clone());
+                self.save();
+                format!("NEW BEST {}", format_time(seconds))
+            },
+        };
+        self.start_attempt();
+        result
+    }
+
+    fn save(&self) {
+        let contents = serde_json::to_string(&self.best_times).unwrap();
+        if let Err(e) = fs::write(BEST_TIMES_PATH, contents) {
+            eprintln!("Unable to save best times to {}: {}", BEST_TIMES_PATH, e);
+        }
+    }
+}
+
+pub fn time_attack_system(
+    time: Res<Time>,
+    mode: Res<GameMode>,
+    keyboard_input: Res<Input<KeyCode>>,
+    paddle_input: Res<PaddleInput>,
+    mut time_attack: ResMut<TimeAttack>,
+    mut levels: ResMut<Levels>,
+    mut current_state: ResMut<CurrentState>,
+    mut applied: Local<Option<GameMode>>,
+    mut lost_reader: Local<EventReader<BallLost>>,
+    lost_events: Res<Events<BallLost>>,
+    mut cleared_reader: Local<EventReader<LevelCleared>>,
+    cleared_events: Res<Events<LevelCleared>>,
+    mut reset_events: ResMut<Events<ResetLevel>>,
+) {
+    let entered = *mode == GameMode::TimeAttack && *applied != Some(GameMode::TimeAttack);
+    *applied = Some(*mode);
+
+    let balls_lost = lost_reader.iter(&lost_events).count();
+    let cleared = cleared_reader.iter(&cleared_events).last().is_some();
+    if *mode != GameMode::TimeAttack {
+        return;
+    }
+
+    if entered {
+        time_attack.start_attempt();
+        time_attack.result = None;
+        return;
+    }
+
+    // Levels are picked on the start screen before the clock starts
+    let started = time_attack.play_seconds > 0.0;
+    if current_state.state == GameState::ArenaStart && !started {
+        let count = levels.levels.len();
+        let mut picked = None;
+        if keyboard_input.just_pressed(KeyCode::BracketLeft) {
+            picked = Some((levels.current + count - 1) % count);
+        }
+        if keyboard_input.just_pressed(KeyCode::BracketRight) {
+            picked = Some((levels.current + 1) % count);
+        }
+        if let Some(picked) = picked {
+            levels.current = picked;
+            time_attack.result = None;
+            reset_events.send(ResetLevel);
+        }
+    }
+
+    if current_state.state == GameState::Playing {
+        time_attack.result = None;
+
+        // The paddle moves with this frame's input from the current time on
+        let direction = paddle_input.direction[0] as i8;
+        if direction != time_attack.last_direction {
+            let at = time_attack.play_seconds;
+            time_attack.recording.inputs.push((at, direction));
+            time_attack.last_direction = direction;
+        }
+        time_attack.play_seconds += time.delta_seconds;
+    }
+
+    for _ in 0..balls_lost {
+        time_attack.penalty_seconds += BALL_LOST_PENALTY_SECONDS;
+        let at = time_attack.play_seconds;
+        time_attack.recording.resets.push(at);
+    }
+
+    if cleared && current_state.state == GameState::Playing {
+        let name = levels.current().name.clone();
+        time_attack.result = Some(time_attack.finish(&name));
+        current_state.set(GameState::ArenaStart);
+        reset_events.send(ResetLevel);
+    }
+}
+
+pub struct GhostPaddle;
+
+// Shadows the best run on the current level, when there is one
+pub fn ghost_paddle_system(
+    mut commands: Commands,
+    mode: Res<GameMode>,
+    levels: Res<Levels>,
+    asset_server: Res<AssetServer>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut time_attack: ResMut<TimeAttack>,
+    mut paddles: Query<&Paddle>,
+    mut ghosts: Query<(Entity, &GhostPaddle, Mut<Translation>)>,
+) {
+    let time_attack = &mut *time_attack;
+    let recording = if *mode == GameMode::TimeAttack {
+        time_attack.best_times.levels.get(&levels.current().name)
+    } else {
+        None
+    };
+
+    let recording = match recording {
+        Some(recording) => recording,
+        None => {
+            for (entity, _ghost, _translation) in &mut ghosts.iter() {
+                commands.despawn(entity);
+            }
+            return;
+        },
+    };
+
+    let mut home_z = None;
+    let mut speed = 0.0;
+    for paddle in &mut paddles.iter() {
+        if paddle.player == 0 {
+            home_z = Some(paddle.home_z);
+            speed = paddle.speed;
+        }
+    }
+    let home_z = match home_z {
+        Some(home_z) => home_z,
+        None => return,
+    };
+
+    let until = time_attack.play_seconds;
+    time_attack.ghost.advance(recording, until, speed);
+
+    // Hidden once the best run would already have finished
+    let best_play_seconds = recording.seconds - recording.resets.len() as f32 * BALL_LOST_PENALTY_SECONDS;
+    let finished = until > 0.0 && until >= best_play_seconds;
+    let position = if finished {
+        Vec3::new(0.0, GHOST_HIDDEN_Y, 0.0)
+    } else {
+        Vec3::new(time_attack.ghost.x, 3.0, home_z + GHOST_Z_OFFSET)
+    };
+
+    let mut spawned = false;
+    for (_entity, _ghost, mut translation) in &mut ghosts.iter() {
+        translation.0 = position;
+        spawned = true;
+    }
+    if !spawned {
+        commands
+            .spawn(PbrComponents {
+                mesh: asset_server
+                    .load("assets/blender/paddle/export/paddle.gltf")
+                    .unwrap(),
+                material: materials.add(Color::rgb(0.25, 0.3, 0.7).into()),
+                translation: Translation(position),
+                rotation: Rotation(Quat::from_rotation_y(PI)),
+                ..Default::default()
+            })
+            .with(GhostPaddle);
+    }
+}
@@ -0,0 +1,29 @@
+use bevy::prelude::*;
+
+// UI was laid out against a 1080 pixel tall window
+const DESIGN_HEIGHT: f32 = 1080.0;
+
+// Text whose size follows the window's height
+pub struct ScaledText {
+    pub font_size: f32,
+}
+
+pub fn ui_scale_system(
+    windows: Res<Windows>,
+    mut last_height: Local<u32>,
+    mut query: Query<(Mut<Text>, &ScaledText)>,
+) {
+    let height = match windows.get_primary() {
+        Some(window) => window.height,
+        None => return,
+    };
+    if height == *last_height {
+        return;
+    }
+    *last_height = height;
+
+    let scale = height as f32 / DESIGN_HEIGHT;
+    for (mut text, scaled) in &mut query.iter() {
+        text.style.font_size = scaled.font_size * scale;
+    }
+}